@@ -1,49 +1,324 @@
+use rox_lib::bytecode::is_bytecode;
+use rox_lib::compiler::compile;
+use rox_lib::debug::disassemble_program;
+use rox_lib::heap::Heap;
 use rox_lib::vm::{InterpretResult, VM};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
 use std::env;
 use std::fs;
 use std::io;
-use std::io::Write;
-use std::path::Path;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::Instant;
+
+//Where the script to run comes from
+enum Script<'a> {
+    File(&'a str),
+    Source(&'a str), //Given with -e
+    Stdin,
+}
 
 fn main() {
     let mut vm = VM::new();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
 
-    if env::args().len() == 1 {
-        repl(&mut vm);
-    } else if env::args().len() == 2 {
-        run_file(&mut vm, &env::args().collect::<Vec<_>>()[1]);
-    } else {
-        eprintln!("Usage: rox [path]");
-        process::exit(64);
+    //Options come first, anything after the script is passed on to it
+    let mut disassemble = false;
+    let mut script = None;
+    let mut rest = args.as_slice();
+    while let Some((&arg, tail)) = rest.split_first() {
+        match arg {
+            "--trace" => vm.set_trace_execution(true),
+            "--disassemble" | "--dump" => disassemble = true,
+            "--version" => {
+                println!("rox {}", env!("CARGO_PKG_VERSION"));
+                process::exit(0);
+            }
+            "-e" => match tail.split_first() {
+                Some((&code, tail)) => {
+                    script = Some(Script::Source(code));
+                    rest = tail;
+                    break;
+                }
+                None => usage(),
+            },
+            "-" => {
+                script = Some(Script::Stdin);
+                rest = tail;
+                break;
+            }
+            _ if arg.starts_with('-') => usage(),
+            _ => break,
+        }
+        rest = tail;
+    }
+    vm.set_print_code(disassemble);
+
+    let (script, script_args) = match (script, rest) {
+        (Some(script), script_args) => (script, script_args),
+        (None, []) => {
+            vm.set_args(&[]);
+            repl(&mut vm);
+        }
+        (None, ["compile", path]) => {
+            compile_file(path, Path::new(path).with_extension("roxc"), disassemble);
+            process::exit(0);
+        }
+        (None, ["compile", path, "-o", output]) => {
+            compile_file(path, output, disassemble);
+            process::exit(0);
+        }
+        (None, ["compile", ..]) => usage(),
+        (None, ["run", path, script_args @ ..]) => (Script::File(path), script_args),
+        (None, [path, script_args @ ..]) => (Script::File(path), script_args),
+    };
+
+    let script_args: Vec<String> = script_args.iter().map(|a| a.to_string()).collect();
+    vm.set_args(&script_args);
+
+    match script {
+        Script::File(path) => run(&mut vm, path, read_file(path)),
+        Script::Source(code) => run(&mut vm, "-e", code.as_bytes().to_vec()),
+        Script::Stdin => {
+            let mut bytes = Vec::new();
+            if let Err(e) = io::stdin().read_to_end(&mut bytes) {
+                eprintln!("Could not read from stdin.\n{}", e);
+                process::exit(74);
+            }
+            run(&mut vm, "<stdin>", bytes)
+        }
     }
 }
 
+fn usage() -> ! {
+    eprintln!("Usage: rox [options] [path [args...]]");
+    eprintln!("       rox [options] run <path> [args...]");
+    eprintln!("       rox [options] -e <code> [args...]");
+    eprintln!("       rox [options] - [args...]");
+    eprintln!("       rox [--disassemble] compile <path> [-o <output>]");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("  --trace        Print the stack and each instruction as it runs");
+    eprintln!("  --disassemble  Print the compiled bytecode before running it");
+    eprintln!("  --version      Print the version and exit");
+    process::exit(64);
+}
+
+//Reads entries until Ctrl-D, prompting for more lines while an entry is incomplete, such as an
+//open block or string. Ctrl-C throws away the entry being typed
 fn repl(vm: &mut VM) -> ! {
+    let mut editor = match DefaultEditor::new() {
+        Ok(e) => e,
+        Err(e) => {
+            eprintln!("Could not start the REPL.\n{}", e);
+            process::exit(74);
+        }
+    };
+    let history = history_path();
+    if let Some(path) = &history {
+        //There is no history yet the first time the REPL is run
+        let _ = editor.load_history(path);
+    }
+
+    let mut buffer = String::new();
     loop {
-        print!("> ");
-        io::stdout().flush().unwrap();
+        let prompt = if buffer.is_empty() { "> " } else { "... " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                buffer.push_str(&line);
+                buffer.push('\n');
+            }
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("Could not read from stdin.\n{}", e);
+                process::exit(74);
+            }
+        }
+
+        if buffer.trim().is_empty() {
+            buffer.clear();
+            continue;
+        }
+
+        if buffer.starts_with(':') {
+            let _ = editor.add_history_entry(buffer.trim_end());
+            command(vm, buffer.trim());
+            buffer.clear();
+            continue;
+        }
+
+        let result = vm.interpret_repl(&buffer);
+        if let InterpretResult::CompileError(errors) = &result {
+            if errors.iter().all(|e| e.is_at_end(&buffer)) {
+                continue;
+            }
+        }
+        report_errors(vm, &result, &buffer);
+
+        let _ = editor.add_history_entry(buffer.trim_end());
+        buffer.clear();
+    }
+
+    if let Some(path) = &history {
+        if let Err(e) = editor.save_history(path) {
+            eprintln!("Could not write file '{}'.\n{}", path.display(), e);
+        }
+    }
+    process::exit(0);
+}
 
-        let mut line = String::new();
-        io::stdin().read_line(&mut line).unwrap();
+//Runs a REPL meta-command such as ':globals'
+fn command(vm: &mut VM, line: &str) {
+    let (name, argument) = match line.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (line, ""),
+    };
 
-        vm.interpret(&line);
+    match (name, argument) {
+        (":help", "") => {
+            println!(":globals       List every global and its value");
+            println!(":dis <expr>    Print the bytecode compiled for an expression");
+            println!(":type <expr>   Print the type of an expression's value");
+            println!(":load <path>   Run a script in this session");
+            println!(":reset         Forget every global defined so far");
+            println!(":time <stmt>   Run statements and print how long they took");
+            println!(":help          Print this help");
+        }
+        (":globals", "") => {
+            for (name, value) in vm.describe_globals() {
+                println!("{} = {}", name, value);
+            }
+        }
+        (":dis", expression) if !expression.is_empty() => match vm.compile_expression(expression) {
+            Ok(script) => {
+                if let Err(e) = vm.disassemble(&mut io::stdout(), &script) {
+                    eprintln!("Could not write to stdout.\n{}", e);
+                }
+                vm.discard(script);
+            }
+            Err(errors) => report_errors(vm, &InterpretResult::CompileError(errors), expression),
+        },
+        (":type", expression) if !expression.is_empty() => match vm.evaluate(expression) {
+            Ok(value) => println!("{}", value.type_name()),
+            Err(result) => report_errors(vm, &result, expression),
+        },
+        (":load", path) if !path.is_empty() => match fs::read_to_string(path) {
+            Ok(source) => {
+                let result = vm.interpret(&source);
+                report_errors(vm, &result, &source);
+            }
+            Err(e) => eprintln!("Could not open file '{}'.\n{}", path, e),
+        },
+        (":reset", "") => {
+            vm.reset();
+            vm.set_args(&[]);
+        }
+        (":time", statement) if !statement.is_empty() => {
+            let start = Instant::now();
+            let result = vm.interpret_repl(statement);
+            let elapsed = start.elapsed();
+            report_errors(vm, &result, statement);
+            println!("Took {:.3}ms", elapsed.as_secs_f64() * 1000.0);
+        }
+        _ => eprintln!("Unknown command '{}', see :help.", line),
     }
 }
 
-fn run_file<P: AsRef<Path>>(vm: &mut VM, path: P) {
-    let source = match fs::read_to_string(&path) {
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| Path::new(&home).join(".rox_history"))
+}
+
+fn read_file<P: AsRef<Path>>(path: P) -> Vec<u8> {
+    match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Could not open file '{}'.\n{}", path.as_ref().display(), e);
+            process::exit(74);
+        }
+    }
+}
+
+fn read_source<P: AsRef<Path>>(path: P, bytes: Vec<u8>) -> String {
+    match String::from_utf8(bytes) {
         Ok(s) => s,
         Err(e) => {
             eprintln!("Could not open file '{}'.\n{}", path.as_ref().display(), e);
             process::exit(74);
         }
+    }
+}
+
+fn compile_file<P: AsRef<Path>, Q: AsRef<Path>>(path: P, output: Q, dump: bool) {
+    let source = read_source(&path, read_file(&path));
+
+    let mut heap = Heap::new();
+    let chunk = match compile(&source, &mut heap) {
+        Ok(c) => c,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}", error.render(&source));
+            }
+            process::exit(65);
+        }
     };
 
-    let result = vm.interpret(&source);
+    if dump {
+        disassemble_program(&mut io::stdout(), &chunk, &heap).unwrap();
+    }
+
+    let bytes = match chunk.serialize(&heap) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(65);
+        }
+    };
+
+    if let Err(e) = fs::write(&output, bytes) {
+        eprintln!(
+            "Could not write file '{}'.\n{}",
+            output.as_ref().display(),
+            e
+        );
+        process::exit(74);
+    }
+}
+
+//Runs either source code or bytecode written by 'rox compile', told apart by the .roxc extension
+//or its header. Any .roxc file is loaded as bytecode so that a damaged one is reported as such
+fn run(vm: &mut VM, name: &str, bytes: Vec<u8>) -> ! {
+    let compiled = Path::new(name).extension().is_some_and(|e| e == "roxc");
+    let (result, source) = if compiled || is_bytecode(&bytes) {
+        match vm.load(&bytes) {
+            Ok(script) => (vm.run_loaded(script), String::new()),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(65);
+            }
+        }
+    } else {
+        let source = read_source(name, bytes);
+        (vm.interpret(&source), source)
+    };
+    report_errors(vm, &result, &source);
+
     match result {
-        InterpretResult::CompileError => process::exit(65),
-        InterpretResult::RuntimeError => process::exit(70),
+        InterpretResult::CompileError(_) => process::exit(65),
+        InterpretResult::RuntimeError(_) => process::exit(70),
         InterpretResult::Ok => process::exit(0),
     }
 }
+
+//The VM writes errors to stderr, which there is nothing more to do about if it fails
+fn report_errors(vm: &mut VM, result: &InterpretResult, source: &str) {
+    let _ = vm.report(result, source);
+}
@@ -0,0 +1,469 @@
+use crate::chunk::{Chunk, LineRun};
+use crate::heap::Heap;
+use crate::object::{Function, ObjectType};
+use crate::value::Value;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+//File layout, all integers little-endian:
+//  header    "ROXC" magic, u16 version, then the script chunk
+//  chunk     u32 length + code bytes, u32 count + constants, u32 count + line runs
+//  constant  u8 tag followed by its payload, functions embed their own chunk
+//  line run  u32 start, i32 line
+pub const MAGIC: &[u8; 4] = b"ROXC";
+pub const VERSION: u16 = 2;
+
+const TAG_NIL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_NUMBER: u8 = 3;
+const TAG_STRING: u8 = 4;
+const TAG_FUNCTION: u8 = 5;
+
+//Bounds the recursion when loading nested functions from untrusted input
+const MAX_NESTING: usize = 256;
+
+#[derive(Debug, PartialEq)]
+pub struct BytecodeError {
+    pub message: String,
+}
+
+impl BytecodeError {
+    pub fn new(message: &str) -> Self {
+        BytecodeError {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for BytecodeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Invalid bytecode: {}", self.message)
+    }
+}
+
+impl Error for BytecodeError {}
+
+//Whether the bytes start with the bytecode file magic and the supported version, used to tell
+//compiled files from source. The version bytes can't appear in source, which may start with ROXC
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC) && bytes[MAGIC.len()..].starts_with(&VERSION.to_le_bytes())
+}
+
+impl Chunk {
+    pub fn serialize(&self, heap: &Heap) -> Result<Vec<u8>, BytecodeError> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        write_chunk(&mut out, self, heap)?;
+        Ok(out)
+    }
+
+    //Strings are interned and functions allocated in the heap, as if the chunk had been compiled
+    pub fn deserialize(bytes: &[u8], heap: &mut Heap) -> Result<Chunk, BytecodeError> {
+        let mut reader = Reader { bytes, position: 0 };
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(BytecodeError::new("Missing 'ROXC' header."));
+        }
+
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(BytecodeError::new(&format!(
+                "Unsupported version {}, expected {}.",
+                version, VERSION
+            )));
+        }
+
+        let chunk = reader.chunk(heap, 0)?;
+
+        if reader.position != bytes.len() {
+            return Err(BytecodeError::new(&format!(
+                "Unexpected trailing data at byte {}.",
+                reader.position
+            )));
+        }
+
+        Ok(chunk)
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, value: usize) -> Result<(), BytecodeError> {
+    if value > u32::MAX as usize {
+        return Err(BytecodeError::new(
+            "Value too large for the bytecode format.",
+        ));
+    }
+    out.extend_from_slice(&(value as u32).to_le_bytes());
+    Ok(())
+}
+
+fn write_string(out: &mut Vec<u8>, string: &str) -> Result<(), BytecodeError> {
+    write_u32(out, string.len())?;
+    out.extend_from_slice(string.as_bytes());
+    Ok(())
+}
+
+fn write_chunk(out: &mut Vec<u8>, chunk: &Chunk, heap: &Heap) -> Result<(), BytecodeError> {
+    write_u32(out, chunk.code.len())?;
+    out.extend_from_slice(&chunk.code);
+
+    write_u32(out, chunk.constants.len())?;
+    for constant in &chunk.constants {
+        write_constant(out, *constant, heap)?;
+    }
+
+    write_u32(out, chunk.lines.len())?;
+    for run in &chunk.lines {
+        out.extend_from_slice(&run.start.to_le_bytes());
+        out.extend_from_slice(&run.line.to_le_bytes());
+    }
+
+    Ok(())
+}
+
+fn write_constant(out: &mut Vec<u8>, value: Value, heap: &Heap) -> Result<(), BytecodeError> {
+    match value {
+        Value::Nil => out.push(TAG_NIL),
+        Value::Boolean(false) => out.push(TAG_FALSE),
+        Value::Boolean(true) => out.push(TAG_TRUE),
+        Value::Number(n) => {
+            out.push(TAG_NUMBER);
+            out.extend_from_slice(&n.to_bits().to_le_bytes());
+        }
+        Value::Object(ObjectType::String(s)) => {
+            out.push(TAG_STRING);
+            let string: &String = heap.get(s);
+            write_string(out, string)?;
+        }
+        Value::Object(ObjectType::Function(f)) => {
+            let function = heap.get(f);
+            out.push(TAG_FUNCTION);
+            match &function.name {
+                Some(name) => {
+                    out.push(1);
+                    write_string(out, name)?;
+                }
+                None => out.push(0),
+            }
+            write_u32(out, function.arity)?;
+            write_u32(out, function.upvalue_count)?;
+            write_chunk(out, &function.chunk, heap)?;
+        }
+        //Only created at runtime, the compiler never puts these in a constant table
+        Value::Object(_) => {
+            return Err(BytecodeError::new(&format!(
+                "Cannot serialize constant '{}'.",
+                value.display(heap)
+            )))
+        }
+    }
+
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], BytecodeError> {
+        if self.bytes.len() - self.position < length {
+            return Err(BytecodeError::new(&format!(
+                "Unexpected end of input at byte {}.",
+                self.bytes.len()
+            )));
+        }
+
+        let bytes = &self.bytes[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, BytecodeError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<usize, BytecodeError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    fn i32(&mut self) -> Result<i32, BytecodeError> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn f64(&mut self) -> Result<f64, BytecodeError> {
+        let mut bits = [0; 8];
+        bits.copy_from_slice(self.take(8)?);
+        Ok(f64::from_bits(u64::from_le_bytes(bits)))
+    }
+
+    fn string(&mut self) -> Result<String, BytecodeError> {
+        let start = self.position;
+        let length = self.u32()?;
+        match std::str::from_utf8(self.take(length)?) {
+            Ok(s) => Ok(s.to_string()),
+            Err(_) => Err(BytecodeError::new(&format!(
+                "Invalid UTF-8 in string at byte {}.",
+                start
+            ))),
+        }
+    }
+
+    fn chunk(&mut self, heap: &mut Heap, depth: usize) -> Result<Chunk, BytecodeError> {
+        if depth > MAX_NESTING {
+            return Err(BytecodeError::new("Functions nested too deeply."));
+        }
+
+        let mut chunk = Chunk::new();
+
+        let code_length = self.u32()?;
+        chunk.code = self.take(code_length)?.to_vec();
+
+        //Counts are untrusted, so grow as entries are read rather than reserving up front
+        let constant_count = self.u32()?;
+        for _ in 0..constant_count {
+            let constant = self.constant(heap, depth)?;
+            chunk.constants.push(constant);
+        }
+
+        let run_count = self.u32()?;
+        for _ in 0..run_count {
+            let position = self.position;
+            let start = self.u32()?;
+            let line = self.i32()?;
+
+            let in_order = match chunk.lines.last() {
+                Some(previous) => start > previous.start as usize,
+                None => start == 0,
+            };
+            if !in_order || start >= chunk.code.len() {
+                return Err(BytecodeError::new(&format!(
+                    "Line run out of order at byte {}.",
+                    position
+                )));
+            }
+
+            chunk.lines.push(LineRun {
+                start: start as u32,
+                line,
+            });
+        }
+
+        if chunk.lines.is_empty() && !chunk.code.is_empty() {
+            return Err(BytecodeError::new("Missing line table for code."));
+        }
+
+        Ok(chunk)
+    }
+
+    fn constant(&mut self, heap: &mut Heap, depth: usize) -> Result<Value, BytecodeError> {
+        let position = self.position;
+
+        let value = match self.u8()? {
+            TAG_NIL => Value::Nil,
+            TAG_FALSE => Value::Boolean(false),
+            TAG_TRUE => Value::Boolean(true),
+            TAG_NUMBER => Value::Number(self.f64()?),
+            TAG_STRING => {
+                let string = self.string()?;
+                Value::Object(ObjectType::String(heap.intern_owned(string)))
+            }
+            TAG_FUNCTION => {
+                let name = match self.u8()? {
+                    0 => None,
+                    1 => Some(self.string()?),
+                    _ => {
+                        return Err(BytecodeError::new(&format!(
+                            "Invalid function name flag at byte {}.",
+                            position + 1
+                        )))
+                    }
+                };
+                let arity = self.u32()?;
+                let upvalue_count = self.u32()?;
+                let chunk = self.chunk(heap, depth + 1)?;
+
+                let mut function = Function::new(name, arity, chunk);
+                function.upvalue_count = upvalue_count;
+                Value::Object(ObjectType::Function(heap.alloc(function)))
+            }
+            tag => {
+                return Err(BytecodeError::new(&format!(
+                    "Unknown constant tag {} at byte {}.",
+                    tag, position
+                )))
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+
+    fn compiled(source: &str, heap: &mut Heap) -> Vec<u8> {
+        compile(source, heap).unwrap().serialize(heap).unwrap()
+    }
+
+    #[test]
+    fn bytecode_round_trip() {
+        let mut heap = Heap::new();
+        let source = "var a = \"hi\"; fun f(x) { return x + 1.5; } print f(2) == nil or true;";
+        let chunk = compile(source, &mut heap).unwrap();
+        let bytes = chunk.serialize(&heap).unwrap();
+
+        let mut loaded_heap = Heap::new();
+        let result = Chunk::deserialize(&bytes, &mut loaded_heap).unwrap();
+
+        assert_eq!(result.code, chunk.code);
+        assert_eq!(result.lines, chunk.lines);
+        assert_eq!(result.constants.len(), chunk.constants.len());
+        for (loaded, original) in result.constants.iter().zip(&chunk.constants) {
+            assert_eq!(
+                loaded.display(&loaded_heap).to_string(),
+                original.display(&heap).to_string()
+            );
+        }
+        assert_eq!(result.serialize(&loaded_heap).unwrap(), bytes);
+    }
+
+    #[test]
+    fn bytecode_deserialize_interns_strings() {
+        let mut heap = Heap::new();
+        let bytes = compiled("print \"abc\";", &mut heap);
+
+        let mut loaded_heap = Heap::new();
+        let chunk = Chunk::deserialize(&bytes, &mut loaded_heap).unwrap();
+
+        let abc = loaded_heap.intern("abc");
+        assert!(chunk
+            .constants
+            .contains(&Value::Object(ObjectType::String(abc))));
+    }
+
+    #[test]
+    fn bytecode_deserialize_function() {
+        let mut heap = Heap::new();
+        let bytes = compiled("fun add(a, b) { return a + b; }", &mut heap);
+
+        let mut loaded_heap = Heap::new();
+        let chunk = Chunk::deserialize(&bytes, &mut loaded_heap).unwrap();
+
+        let function = chunk
+            .constants
+            .iter()
+            .find_map(|c| match c {
+                Value::Object(ObjectType::Function(f)) => Some(loaded_heap.get(*f)),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(function.name.as_deref(), Some("add"));
+        assert_eq!(function.arity, 2);
+        assert!(!function.chunk.code.is_empty());
+    }
+
+    #[test]
+    fn bytecode_rejects_every_truncation() {
+        let mut heap = Heap::new();
+        let bytes = compiled("fun f() { return \"x\"; } print f();", &mut heap);
+
+        for length in 0..bytes.len() {
+            assert!(Chunk::deserialize(&bytes[..length], &mut Heap::new()).is_err());
+        }
+    }
+
+    #[test]
+    fn bytecode_is_bytecode() {
+        let mut heap = Heap::new();
+
+        assert!(is_bytecode(&compiled("print 1;", &mut heap)));
+        assert!(!is_bytecode(b"ROXCfoo = 1;"));
+        assert!(!is_bytecode(b"ROXC"));
+        assert!(!is_bytecode(b"print 1;"));
+    }
+
+    #[test]
+    fn bytecode_rejects_bad_magic() {
+        let result = Chunk::deserialize(b"ROXX\x01\x00", &mut Heap::new());
+
+        assert_eq!(
+            result.unwrap_err().message,
+            "Missing 'ROXC' header.".to_string()
+        );
+    }
+
+    #[test]
+    fn bytecode_rejects_other_version() {
+        let mut heap = Heap::new();
+        let mut bytes = compiled("print 1;", &mut heap);
+        bytes[4] = 1;
+
+        let result = Chunk::deserialize(&bytes, &mut Heap::new());
+
+        assert_eq!(
+            result.unwrap_err().message,
+            "Unsupported version 1, expected 2.".to_string()
+        );
+    }
+
+    #[test]
+    fn bytecode_rejects_trailing_data() {
+        let mut heap = Heap::new();
+        let mut bytes = compiled("print 1;", &mut heap);
+        bytes.push(0);
+
+        assert!(Chunk::deserialize(&bytes, &mut Heap::new()).is_err());
+    }
+
+    #[test]
+    fn bytecode_rejects_unknown_tag() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.push(9);
+
+        let result = Chunk::deserialize(&bytes, &mut Heap::new());
+
+        assert_eq!(
+            result.unwrap_err().message,
+            "Unknown constant tag 9 at byte 14.".to_string()
+        );
+    }
+
+    #[test]
+    fn bytecode_rejects_code_without_lines() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+
+        assert!(Chunk::deserialize(&bytes, &mut Heap::new()).is_err());
+    }
+
+    #[test]
+    fn bytecode_serialize_runtime_object() {
+        let mut heap = Heap::new();
+        let mut chunk = Chunk::new();
+        let function = heap.alloc(Function::new(None, 0, Chunk::new()));
+        let closure = heap.alloc(crate::object::Closure::new(function, Vec::new()));
+        chunk.add_constant(Value::Object(ObjectType::Closure(closure)));
+
+        assert!(chunk.serialize(&heap).is_err());
+    }
+}
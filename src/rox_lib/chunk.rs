@@ -1,11 +1,30 @@
 use crate::value::Value;
+use std::convert::TryFrom;
+use std::error::Error;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 
+//Largest constant index addressable by the 24-bit operand of the long instructions
+pub const MAX_LONG_OPERAND: usize = 0xFF_FFFF;
+
+#[derive(Debug)]
 pub struct Chunk {
     pub code: Vec<u8>,
     pub constants: Vec<Value>,
-    pub lines: Vec<i32>,
+    pub lines: Vec<LineRun>, //Source line of the bytes in code, a run for each line
+}
+
+//Every byte from start up to the start of the next run comes from the same line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineRun {
+    pub start: u32,
+    pub line: i32,
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Chunk {
@@ -18,8 +37,21 @@ impl Chunk {
     }
 
     pub fn write(&mut self, byte: u8, line: i32) {
+        if self.lines.last().map(|run| run.line) != Some(line) {
+            self.lines.push(LineRun {
+                start: self.code.len() as u32,
+                line,
+            });
+        }
         self.code.push(byte);
-        self.lines.push(line);
+    }
+
+    //Chunks built by hand may have no lines, their code is reported on line 0
+    pub fn get_line(&self, offset: usize) -> i32 {
+        let run = self
+            .lines
+            .partition_point(|run| run.start as usize <= offset);
+        run.checked_sub(1).map_or(0, |run| self.lines[run].line)
     }
 
     pub fn add_constant(&mut self, value: Value) -> usize {
@@ -28,100 +60,165 @@ impl Chunk {
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub enum OpCode {
-    Constant,
-    Nil,
-    True,
-    False,
-    Pop,
-    GetLocal,
-    SetLocal,
-    GetGlobal,
-    DefineGlobal,
-    SetGlobal,
-    Equal,
-    Greater,
-    Less,
-    Add,
-    Subtract,
-    Multiple,
-    Divide,
-    Not,
-    Negate,
-    Print,
-    Jump,
-    JumpIfFalse,
-    Loop,
-    Return,
+//The operands that follow an opcode in the code
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    None,
+    Byte,         //Stack slot, upvalue index or argument count
+    Constant,     //Index into the constant table
+    ConstantLong, //24-bit big-endian index into the constant table
+    Jump,         //16-bit big-endian forward offset
+    Loop,         //16-bit big-endian backward offset
+    Closure,      //Function constant, then a local flag and index byte for each of its upvalues
+    ClosureLong,  //24-bit function constant, then the upvalues as for Closure
+}
+
+impl Operand {
+    //Bytes taken by the operand, not counting the upvalue pairs following a closure
+    pub fn width(&self) -> usize {
+        match self {
+            Operand::None => 0,
+            Operand::Byte | Operand::Constant | Operand::Closure => 1,
+            Operand::Jump | Operand::Loop => 2,
+            Operand::ConstantLong | Operand::ClosureLong => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodeError {
+    UnknownOpcode(u8),
+    Truncated(OpCode),  //The code ends before all of the instruction's operands
+    InvalidCapture(u8), //A closure capture flag that is neither local nor upvalue
 }
 
-impl From<u8> for OpCode {
-    fn from(byte: u8) -> Self {
-        match byte {
-            0 => OpCode::Constant,
-            1 => OpCode::Nil,
-            2 => OpCode::True,
-            3 => OpCode::False,
-            4 => OpCode::Pop,
-            5 => OpCode::GetLocal,
-            6 => OpCode::SetLocal,
-            7 => OpCode::GetGlobal,
-            8 => OpCode::DefineGlobal,
-            9 => OpCode::SetGlobal,
-            10 => OpCode::Equal,
-            11 => OpCode::Greater,
-            12 => OpCode::Less,
-            13 => OpCode::Add,
-            14 => OpCode::Subtract,
-            15 => OpCode::Multiple,
-            16 => OpCode::Divide,
-            17 => OpCode::Not,
-            18 => OpCode::Negate,
-            19 => OpCode::Print,
-            20 => OpCode::Jump,
-            21 => OpCode::JumpIfFalse,
-            22 => OpCode::Loop,
-            23 => OpCode::Return,
-            _ => panic!("Unknown Opcode"),
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            DecodeError::UnknownOpcode(byte) => write!(f, "Unknown opcode {}.", byte),
+            DecodeError::Truncated(opcode) => write!(f, "Truncated operand for {}.", opcode),
+            DecodeError::InvalidCapture(flag) => write!(f, "Invalid capture flag {}.", flag),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+//Declares every opcode once, with its byte, mnemonic and operand
+macro_rules! opcodes {
+    ($($name:ident = $byte:literal, $mnemonic:literal, $operand:ident;)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub enum OpCode {
+            $($name = $byte,)*
+        }
+
+        impl TryFrom<u8> for OpCode {
+            type Error = DecodeError;
+
+            fn try_from(byte: u8) -> Result<Self, Self::Error> {
+                match byte {
+                    $($byte => Ok(OpCode::$name),)*
+                    _ => Err(DecodeError::UnknownOpcode(byte)),
+                }
+            }
+        }
+
+        impl OpCode {
+            pub fn mnemonic(&self) -> &'static str {
+                match self {
+                    $(OpCode::$name => $mnemonic,)*
+                }
+            }
+
+            pub fn operand(&self) -> Operand {
+                match self {
+                    $(OpCode::$name => Operand::$operand,)*
+                }
+            }
+        }
+    };
+}
+
+opcodes! {
+    Constant = 0, "CONSTANT", Constant;
+    Nil = 1, "NIL", None;
+    True = 2, "TRUE", None;
+    False = 3, "FALSE", None;
+    Pop = 4, "POP", None;
+    GetLocal = 5, "GET LOCAL", Byte;
+    SetLocal = 6, "SET LOCAL", Byte;
+    GetGlobal = 7, "GET GLOBAL", Constant;
+    DefineGlobal = 8, "DEFINE GLOBAL", Constant;
+    SetGlobal = 9, "SET GLOBAL", Constant;
+    Equal = 10, "EQUAL", None;
+    Greater = 11, "GREATER", None;
+    Less = 12, "LESS", None;
+    Add = 13, "ADD", None;
+    Subtract = 14, "SUBTRACT", None;
+    Multiple = 15, "MULTIPLE", None;
+    Divide = 16, "DIVIDE", None;
+    Not = 17, "NOT", None;
+    Negate = 18, "NEGATE", None;
+    Print = 19, "PRINT", None;
+    Jump = 20, "JUMP", Jump;
+    JumpIfFalse = 21, "JUMP IF FALSE", Jump;
+    Loop = 22, "LOOP", Loop;
+    GetUpvalue = 23, "GET UPVALUE", Byte;
+    SetUpvalue = 24, "SET UPVALUE", Byte;
+    GetProperty = 25, "GET PROPERTY", Constant;
+    SetProperty = 26, "SET PROPERTY", Constant;
+    GetSuper = 27, "GET SUPER", Constant;
+    Call = 28, "CALL", Byte;
+    Closure = 29, "CLOSURE", Closure;
+    CloseUpvalue = 30, "CLOSE UPVALUE", None;
+    Return = 31, "RETURN", None;
+    Class = 32, "CLASS", Constant;
+    Inherit = 33, "INHERIT", None;
+    Method = 34, "METHOD", Constant;
+    ConstantLong = 35, "CONSTANT LONG", ConstantLong;
+    GetGlobalLong = 36, "GET GLOBAL LONG", ConstantLong;
+    DefineGlobalLong = 37, "DEFINE GLOBAL LONG", ConstantLong;
+    SetGlobalLong = 38, "SET GLOBAL LONG", ConstantLong;
+    GetPropertyLong = 39, "GET PROPERTY LONG", ConstantLong;
+    SetPropertyLong = 40, "SET PROPERTY LONG", ConstantLong;
+    GetSuperLong = 41, "GET SUPER LONG", ConstantLong;
+    ClosureLong = 42, "CLOSURE LONG", ClosureLong;
+    ClassLong = 43, "CLASS LONG", ConstantLong;
+    MethodLong = 44, "METHOD LONG", ConstantLong;
+}
+
+impl OpCode {
+    //Variant taking a 24-bit operand, for instructions that index the constant table
+    pub fn long_form(&self) -> Option<OpCode> {
+        match self {
+            OpCode::Constant => Some(OpCode::ConstantLong),
+            OpCode::GetGlobal => Some(OpCode::GetGlobalLong),
+            OpCode::DefineGlobal => Some(OpCode::DefineGlobalLong),
+            OpCode::SetGlobal => Some(OpCode::SetGlobalLong),
+            OpCode::GetProperty => Some(OpCode::GetPropertyLong),
+            OpCode::SetProperty => Some(OpCode::SetPropertyLong),
+            OpCode::GetSuper => Some(OpCode::GetSuperLong),
+            OpCode::Closure => Some(OpCode::ClosureLong),
+            OpCode::Class => Some(OpCode::ClassLong),
+            OpCode::Method => Some(OpCode::MethodLong),
+            _ => None,
         }
     }
 }
 
 impl Display for OpCode {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        f.pad(match self {
-            OpCode::Constant => "CONSTANT",
-            OpCode::Nil => "NIL",
-            OpCode::True => "TRUE",
-            OpCode::False => "FALSE",
-            OpCode::Pop => "POP",
-            OpCode::GetLocal => "GET LOCAL",
-            OpCode::SetLocal => "SET LOCAL",
-            OpCode::GetGlobal => "GET GLOBAL",
-            OpCode::DefineGlobal => "DEFINE GLOBAL",
-            OpCode::SetGlobal => "SET GLOBAL",
-            OpCode::Equal => "EQUAL",
-            OpCode::Greater => "GREATER",
-            OpCode::Less => "LESS",
-            OpCode::Add => "ADD",
-            OpCode::Subtract => "SUBTRACT",
-            OpCode::Multiple => "MULTIPLE",
-            OpCode::Divide => "DIVIDE",
-            OpCode::Not => "NOT",
-            OpCode::Negate => "NEGATE",
-            OpCode::Print => "PRINT",
-            OpCode::Jump => "JUMP",
-            OpCode::JumpIfFalse => "JUMP IF FALSE",
-            OpCode::Loop => "LOOP",
-            OpCode::Return => "RETURN",
-        })
+        f.pad(self.mnemonic())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::compiler::compile;
+    use crate::heap::Heap;
+    use std::fs;
+    use std::mem;
 
     #[test]
     fn chunk_write() {
@@ -130,7 +227,45 @@ mod tests {
         chunk.write(0, 1);
 
         assert_eq!(chunk.code[0], 0);
-        assert_eq!(chunk.lines[0], 1);
+        assert_eq!(chunk.get_line(0), 1);
+    }
+
+    #[test]
+    fn chunk_write_merges_runs() {
+        let mut chunk = Chunk::new();
+
+        chunk.write(0, 1);
+        chunk.write(1, 1);
+        chunk.write(2, 2);
+        chunk.write(3, 2);
+        chunk.write(4, 1);
+
+        assert_eq!(chunk.lines.len(), 3);
+        assert_eq!(chunk.get_line(0), 1);
+        assert_eq!(chunk.get_line(1), 1);
+        assert_eq!(chunk.get_line(2), 2);
+        assert_eq!(chunk.get_line(3), 2);
+        assert_eq!(chunk.get_line(4), 1);
+    }
+
+    #[test]
+    fn chunk_line_table_smaller_than_code() {
+        let mut heap = Heap::new();
+        let source = fs::read_to_string("tests/resources/precedence.lox").unwrap();
+
+        let chunk = compile(&source, &mut heap).unwrap();
+
+        //The table it replaced had an i32 for every byte of code
+        let table = chunk.lines.capacity() * mem::size_of::<LineRun>();
+        assert!(table < 4 * chunk.code.len());
+        assert!(chunk.lines.len() <= source.lines().count());
+    }
+
+    #[test]
+    fn chunk_get_line_without_runs() {
+        let chunk = Chunk::new();
+
+        assert_eq!(chunk.get_line(3), 0);
     }
 
     #[test]
@@ -144,8 +279,42 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Unknown Opcode")]
-    fn opcode_from_invalid_byte_should_panic() {
-        OpCode::from(255);
+    fn opcode_long_form() {
+        assert_eq!(OpCode::Constant.long_form(), Some(OpCode::ConstantLong));
+        assert_eq!(OpCode::SetGlobal.long_form(), Some(OpCode::SetGlobalLong));
+        assert_eq!(OpCode::Closure.long_form(), Some(OpCode::ClosureLong));
+        assert_eq!(OpCode::GetLocal.long_form(), None);
+        assert_eq!(
+            OpCode::try_from(OpCode::ConstantLong as u8),
+            Ok(OpCode::ConstantLong)
+        );
+    }
+
+    #[test]
+    fn opcode_try_from_every_byte() {
+        for byte in 0..=u8::MAX {
+            match OpCode::try_from(byte) {
+                Ok(opcode) => assert_eq!(opcode as u8, byte),
+                Err(error) => assert_eq!(error, DecodeError::UnknownOpcode(byte)),
+            }
+        }
+    }
+
+    #[test]
+    fn opcode_try_from_invalid_byte() {
+        let result = OpCode::try_from(255);
+
+        assert_eq!(result, Err(DecodeError::UnknownOpcode(255)));
+        assert_eq!(result.unwrap_err().to_string(), "Unknown opcode 255.");
+    }
+
+    #[test]
+    fn opcode_table() {
+        assert_eq!(OpCode::JumpIfFalse.mnemonic(), "JUMP IF FALSE");
+        assert_eq!(OpCode::Loop.operand(), Operand::Loop);
+        assert_eq!(OpCode::Closure.operand().width(), 1);
+        assert_eq!(OpCode::SetGlobalLong.operand().width(), 3);
+        assert_eq!(OpCode::ClosureLong.operand().width(), 3);
+        assert_eq!(format!("{:<6}|", OpCode::Nil), "NIL   |");
     }
 }
@@ -1,34 +1,40 @@
-use crate::chunk::{Chunk, OpCode};
-use crate::debug::disassemble_chuck;
-use crate::object::ObjectType;
+use crate::chunk::{Chunk, OpCode, MAX_LONG_OPERAND};
+use crate::heap::Heap;
+use crate::object::{Function, ObjectType};
 use crate::scanner::TokenType::*;
 use crate::scanner::{Scanner, Token, TokenType};
 use crate::value::Value;
-use crate::RoxError;
+use crate::{RoxError, Span};
 use std::ops::{AddAssign, SubAssign};
 use std::str::FromStr;
 
-pub struct Parser {
+pub struct Parser<'a> {
+    heap: &'a mut Heap, //Strings and functions are allocated in the VM's heap as they are compiled
     current: Token,
     previous: Token,
     current_chunk: Chunk,
-    had_error: bool,
+    classes: Vec<ClassCompiler>, //Class declarations currently being compiled, innermost last
+    errors: Vec<RoxError>, //Every error reported while compiling, in the order they were found
     panic_mode: bool,
+    print_expressions: bool, //Print the value of top level expression statements, for the REPL
 }
 
-impl Parser {
-    pub fn new() -> Self {
+impl<'a> Parser<'a> {
+    pub fn new(heap: &'a mut Heap) -> Self {
         Parser {
+            heap,
             current: Token::default(),
             previous: Token::default(),
             current_chunk: Chunk::new(),
-            had_error: false,
+            classes: Vec::new(),
+            errors: Vec::new(),
             panic_mode: false,
+            print_expressions: false,
         }
     }
 
     pub fn emit_byte(&mut self, byte: u8) {
-        self.current_chunk.write(byte, self.previous.line);
+        self.current_chunk.write(byte, self.previous.span.line);
     }
 
     pub fn emit_bytes(&mut self, byte1: u8, byte2: u8) {
@@ -36,6 +42,25 @@ impl Parser {
         self.emit_byte(byte2);
     }
 
+    //Emits the one byte form of the instruction when the operand fits, otherwise the long form
+    //with a 24-bit big-endian operand
+    pub fn emit_operand(&mut self, instruction: OpCode, operand: usize) {
+        if operand <= u8::MAX as usize {
+            self.emit_bytes(instruction as u8, operand as u8);
+        } else if let Some(long) = instruction.long_form() {
+            self.emit_byte(long as u8);
+            self.emit_byte((operand >> 16) as u8);
+            self.emit_byte((operand >> 8) as u8);
+            self.emit_byte(operand as u8);
+        } else {
+            self.handle_error(RoxError::new(
+                "Too many constants in one chunk.",
+                self.previous.lexeme.clone(),
+                self.previous.span,
+            ));
+        }
+    }
+
     pub fn emit_jump(&mut self, instruction: u8) -> usize {
         self.emit_byte(instruction);
         self.emit_byte(0xFF); //Emit dummy address to be patched later
@@ -47,11 +72,11 @@ impl Parser {
         //-2 used to adjust for the bytecode for the jump offset itself
         let jump = self.current_chunk.code.len() - offset - 2;
 
-        if jump > std::u16::MAX as usize {
+        if jump > u16::MAX as usize {
             return Err(RoxError::new(
                 "Too much code to jump over.",
                 self.previous.lexeme.clone(),
-                self.previous.line,
+                self.previous.span,
             ));
         }
 
@@ -65,11 +90,11 @@ impl Parser {
 
         let offset = self.current_chunk.code.len() - loop_start + 2;
 
-        if offset > std::u16::MAX as usize {
+        if offset > u16::MAX as usize {
             return Err(RoxError::new(
                 "Loop body too large.",
                 self.previous.lexeme.clone(),
-                self.previous.line,
+                self.previous.span,
             ));
         }
 
@@ -79,6 +104,17 @@ impl Parser {
         Ok(())
     }
 
+    pub fn emit_return(&mut self, compiler: &Compiler) {
+        //Initializers always return the instance being initialised
+        if compiler.function_type == FunctionType::Initializer {
+            self.emit_bytes(OpCode::GetLocal as u8, 0);
+        } else {
+            self.emit_byte(OpCode::Nil as u8);
+        }
+
+        self.emit_byte(OpCode::Return as u8);
+    }
+
     pub fn check(&self, token_type: TokenType) -> bool {
         self.current.token_type == token_type
     }
@@ -98,9 +134,9 @@ impl Parser {
     }
 
     fn string(&mut self) {
-        self.emit_constant(Value::Object(ObjectType::String(Box::from(
-            self.previous.lexeme.as_str()[1..self.previous.lexeme.len() - 1].to_owned(),
-        ))));
+        let lexeme = &self.previous.lexeme;
+        let string = self.heap.intern(&lexeme[1..lexeme.len() - 1]);
+        self.emit_constant(Value::Object(ObjectType::String(string)));
     }
 
     fn variable(&mut self, scanner: &mut Scanner, compiler: &mut Compiler, can_assign: bool) {
@@ -119,30 +155,86 @@ impl Parser {
         let set_op;
 
         let mut arg = match compiler.resolve_local(&name) {
-            Ok(l) => l,
+            Ok(l) => l.map(usize::from),
             Err(e) => {
                 self.handle_error(e);
                 return;
             }
         };
 
-        if arg != None {
+        if arg.is_some() {
             get_op = OpCode::GetLocal;
             set_op = OpCode::SetLocal;
         } else {
-            arg = Some(self.identifier_constant(name));
-            get_op = OpCode::GetGlobal;
-            set_op = OpCode::SetGlobal;
+            arg = match compiler.resolve_upvalue(&name) {
+                Ok(u) => u.map(usize::from),
+                Err(e) => {
+                    self.handle_error(e);
+                    return;
+                }
+            };
+
+            if arg.is_some() {
+                get_op = OpCode::GetUpvalue;
+                set_op = OpCode::SetUpvalue;
+            } else {
+                arg = Some(self.identifier_constant(name));
+                get_op = OpCode::GetGlobal;
+                set_op = OpCode::SetGlobal;
+            }
         }
 
         if can_assign && match_token(self, scanner, Equal) {
             expression(self, scanner, compiler);
-            self.emit_bytes(set_op as u8, arg.unwrap());
+            self.emit_operand(set_op, arg.unwrap());
         } else {
-            self.emit_bytes(get_op as u8, arg.unwrap());
+            self.emit_operand(get_op, arg.unwrap());
         }
     }
 
+    fn this(&mut self, scanner: &mut Scanner, compiler: &mut Compiler) {
+        if self.classes.is_empty() {
+            self.handle_error(RoxError::new(
+                "Cannot use 'this' outside of a class.",
+                self.previous.lexeme.clone(),
+                self.previous.span,
+            ));
+            return;
+        }
+
+        //'this' is never assignable
+        self.variable(scanner, compiler, false);
+    }
+
+    fn super_(&mut self, scanner: &mut Scanner, compiler: &mut Compiler) {
+        match self.classes.last() {
+            None => self.handle_error(RoxError::new(
+                "Cannot use 'super' outside of a class.",
+                self.previous.lexeme.clone(),
+                self.previous.span,
+            )),
+            Some(class) if !class.has_superclass => self.handle_error(RoxError::new(
+                "Cannot use 'super' in a class with no superclass.",
+                self.previous.lexeme.clone(),
+                self.previous.span,
+            )),
+            Some(_) => (),
+        }
+
+        consume(self, scanner, Dot, "Expect '.' after 'super'.").unwrap_or_else(|e| {
+            self.handle_error(e);
+        });
+        consume(self, scanner, Identifier, "Expect superclass method name.").unwrap_or_else(|e| {
+            self.handle_error(e);
+        });
+        let name = self.identifier_constant(self.previous.clone());
+
+        //Look up the method on the superclass and bind it to the current receiver
+        self.named_variable(scanner, compiler, synthetic_token("this"), false);
+        self.named_variable(scanner, compiler, synthetic_token("super"), false);
+        self.emit_operand(OpCode::GetSuper, name);
+    }
+
     fn unary(&mut self, scanner: &mut Scanner, compiler: &mut Compiler) {
         let operator_type = self.previous.token_type;
 
@@ -175,6 +267,54 @@ impl Parser {
         }
     }
 
+    fn call(&mut self, scanner: &mut Scanner, compiler: &mut Compiler) {
+        let arg_count = self.argument_list(scanner, compiler);
+        self.emit_bytes(OpCode::Call as u8, arg_count);
+    }
+
+    fn dot(&mut self, scanner: &mut Scanner, compiler: &mut Compiler, can_assign: bool) {
+        consume(self, scanner, Identifier, "Expect property name after '.'.").unwrap_or_else(|e| {
+            self.handle_error(e);
+        });
+        let name = self.identifier_constant(self.previous.clone());
+
+        if can_assign && match_token(self, scanner, Equal) {
+            expression(self, scanner, compiler);
+            self.emit_operand(OpCode::SetProperty, name);
+        } else {
+            self.emit_operand(OpCode::GetProperty, name);
+        }
+    }
+
+    fn argument_list(&mut self, scanner: &mut Scanner, compiler: &mut Compiler) -> u8 {
+        let mut arg_count = 0;
+
+        if !self.check(RightParen) {
+            loop {
+                expression(self, scanner, compiler);
+
+                if arg_count == u8::MAX as usize {
+                    self.handle_error(RoxError::new(
+                        "Cannot have more than 255 arguments.",
+                        self.previous.lexeme.clone(),
+                        self.previous.span,
+                    ));
+                }
+                arg_count += 1;
+
+                if !match_token(self, scanner, Comma) {
+                    break;
+                }
+            }
+        }
+
+        consume(self, scanner, RightParen, "Expect ')' after arguments.").unwrap_or_else(|e| {
+            self.handle_error(e);
+        });
+
+        arg_count as u8
+    }
+
     fn and(&mut self, scanner: &mut Scanner, compiler: &mut Compiler) {
         let end_jump = self.emit_jump(OpCode::JumpIfFalse as u8);
 
@@ -217,7 +357,7 @@ impl Parser {
                 self.handle_error(RoxError::new(
                     "Expect expression.",
                     self.previous.lexeme.clone(),
-                    self.previous.line,
+                    self.previous.span,
                 ));
                 return;
             }
@@ -236,7 +376,7 @@ impl Parser {
             self.handle_error(RoxError::new(
                 "Invalid assignment target.",
                 self.previous.lexeme.clone(),
-                self.previous.line,
+                self.previous.span,
             ));
         }
     }
@@ -246,7 +386,7 @@ impl Parser {
         scanner: &mut Scanner,
         compiler: &mut Compiler,
         error_message: &str,
-    ) -> Option<u8> {
+    ) -> Option<usize> {
         consume(self, scanner, Identifier, error_message).unwrap_or_else(|e| {
             self.handle_error(e);
         });
@@ -263,38 +403,39 @@ impl Parser {
         }
     }
 
-    fn identifier_constant(&mut self, name: Token) -> u8 {
-        self.make_constant(Value::Object(ObjectType::String(Box::new(name.lexeme))))
+    fn identifier_constant(&mut self, name: Token) -> usize {
+        let name = self.heap.intern_owned(name.lexeme);
+        self.make_constant(Value::Object(ObjectType::String(name)))
     }
 
-    fn define_variable(&mut self, compiler: &mut Compiler, global: Option<u8>) {
+    fn define_variable(&mut self, compiler: &mut Compiler, global: Option<usize>) {
         match global {
-            Some(g) => self.emit_bytes(OpCode::DefineGlobal as u8, g),
+            Some(g) => self.emit_operand(OpCode::DefineGlobal, g),
             None => {
                 //No bytecode needed at runtime for local variables, just marked as initialised
-                compiler.locals.last_mut().unwrap().depth = compiler.scope_depth;
+                compiler.mark_initialised();
             }
         }
     }
 
     fn emit_constant(&mut self, value: Value) {
         let constant = self.make_constant(value);
-        self.emit_bytes(OpCode::Constant as u8, constant);
+        self.emit_operand(OpCode::Constant, constant);
     }
 
-    fn make_constant(&mut self, value: Value) -> u8 {
+    fn make_constant(&mut self, value: Value) -> usize {
         let constant = self.current_chunk.add_constant(value);
 
-        return if constant > std::u8::MAX as usize {
+        if constant > MAX_LONG_OPERAND {
             self.handle_error(RoxError::new(
                 "Too many constants in one chunk.",
                 self.previous.lexeme.clone(),
-                self.previous.line,
+                self.previous.span,
             ));
             0
         } else {
-            constant as u8
-        };
+            constant
+        }
     }
 
     fn grouping(&mut self, scanner: &mut Scanner, compiler: &mut Compiler) {
@@ -304,34 +445,48 @@ impl Parser {
         });
     }
 
-    pub fn end_compiler(&mut self) {
-        self.emit_byte(OpCode::Return as u8);
-
-        if cfg!(debug_assertions) {
-            if !self.had_error {
-                disassemble_chuck(&self.current_chunk, "code");
-            }
-        }
+    pub fn end_compiler(&mut self, compiler: &Compiler) {
+        self.emit_return(compiler);
     }
 
     pub fn handle_error(&mut self, error: RoxError) {
         if !self.panic_mode {
             self.panic_mode = true;
-            eprintln!("{}", error);
-            self.had_error = true;
+            self.errors.push(error);
         }
     }
 }
 
 pub struct Compiler {
+    enclosing: Option<Box<Compiler>>,
+    function_type: FunctionType,
+    name: Option<String>,
+    arity: usize,
     locals: Vec<Local>,
+    upvalues: Vec<Upvalue>,
     scope_depth: Depth,
 }
 
 impl Compiler {
-    pub fn new() -> Self {
+    pub fn new(function_type: FunctionType) -> Self {
         Compiler {
-            locals: Vec::new(),
+            enclosing: None,
+            function_type,
+            name: None,
+            arity: 0,
+            //Slot zero is reserved for the function being called, or the receiver of a method
+            locals: vec![Local {
+                name: Token {
+                    lexeme: match function_type {
+                        FunctionType::Method | FunctionType::Initializer => "this".to_string(),
+                        FunctionType::Function | FunctionType::Script => String::new(),
+                    },
+                    ..Token::default()
+                },
+                depth: Depth::Global,
+                is_captured: false,
+            }],
+            upvalues: Vec::new(),
             scope_depth: Depth::Global,
         }
     }
@@ -345,7 +500,12 @@ impl Compiler {
 
         //Would love to use self.locals.drain_filter() but it's nightly-only for now
         while matches!(self.locals.last(), Some(l) if l.depth > self.scope_depth) {
-            parser.emit_byte(OpCode::Pop as u8);
+            //Captured variables are moved off the stack so closures can keep using them
+            if self.locals.last().unwrap().is_captured {
+                parser.emit_byte(OpCode::CloseUpvalue as u8);
+            } else {
+                parser.emit_byte(OpCode::Pop as u8);
+            }
             self.locals.pop();
         }
     }
@@ -367,7 +527,7 @@ impl Compiler {
                 return Err(RoxError::new(
                     "Variable with this name already declared in this scope.",
                     name.lexeme.clone(),
-                    name.line,
+                    name.span,
                 ));
             }
         }
@@ -375,22 +535,32 @@ impl Compiler {
         self.add_local(name)
     }
 
+    pub fn mark_initialised(&mut self) {
+        //Global variables are defined at runtime instead
+        if self.scope_depth == Depth::Global {
+            return;
+        }
+
+        self.locals.last_mut().unwrap().depth = self.scope_depth;
+    }
+
     fn add_local(&mut self, name: Token) -> Result<(), RoxError> {
-        return if self.locals.len() > std::u8::MAX as usize {
+        if self.locals.len() > u8::MAX as usize {
             Err(RoxError::new(
                 "Too many local variables in function.",
                 name.lexeme,
-                name.line,
+                name.span,
             ))
         } else {
             let local = Local {
                 name,
                 depth: Depth::Uninitialised,
+                is_captured: false,
             };
 
             self.locals.push(local);
             Ok(())
-        };
+        }
     }
 
     pub fn resolve_local(&self, name: &Token) -> Result<Option<u8>, RoxError> {
@@ -400,7 +570,7 @@ impl Compiler {
                     Err(RoxError::new(
                         "Cannot read local variable in its own initializer.",
                         l.name.lexeme.clone(),
-                        l.name.line,
+                        l.name.span,
                     ))
                 } else {
                     Ok(Some(i as u8))
@@ -410,12 +580,69 @@ impl Compiler {
 
         Ok(None)
     }
+
+    pub fn resolve_upvalue(&mut self, name: &Token) -> Result<Option<u8>, RoxError> {
+        let enclosing = match self.enclosing.as_mut() {
+            Some(e) => e,
+            None => return Ok(None),
+        };
+
+        if let Some(local) = enclosing.resolve_local(name)? {
+            enclosing.locals[local as usize].is_captured = true;
+            return self.add_upvalue(name, local, true).map(Some);
+        }
+
+        if let Some(upvalue) = enclosing.resolve_upvalue(name)? {
+            return self.add_upvalue(name, upvalue, false).map(Some);
+        }
+
+        Ok(None)
+    }
+
+    fn add_upvalue(&mut self, name: &Token, index: u8, is_local: bool) -> Result<u8, RoxError> {
+        //Closures referencing the same variable multiple times share a single upvalue
+        for (i, u) in self.upvalues.iter().enumerate() {
+            if u.index == index && u.is_local == is_local {
+                return Ok(i as u8);
+            }
+        }
+
+        if self.upvalues.len() > u8::MAX as usize {
+            return Err(RoxError::new(
+                "Too many closure variables in function.",
+                name.lexeme.clone(),
+                name.span,
+            ));
+        }
+
+        self.upvalues.push(Upvalue { index, is_local });
+        Ok((self.upvalues.len() - 1) as u8)
+    }
+}
+
+struct ClassCompiler {
+    has_superclass: bool,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum FunctionType {
+    Function,
+    Initializer,
+    Method,
+    Script,
 }
 
 #[derive(Clone)]
 struct Local {
     name: Token,
     depth: Depth,
+    is_captured: bool,
+}
+
+#[derive(Copy, Clone)]
+struct Upvalue {
+    index: u8,
+    is_local: bool, //Whether the upvalue captures a local or an upvalue of the enclosing function
 }
 
 #[derive(Copy, Clone, PartialOrd, PartialEq)]
@@ -444,10 +671,44 @@ impl SubAssign<usize> for Depth {
     }
 }
 
-pub fn compile(source: &str) -> Option<Chunk> {
+pub fn compile(source: &str, heap: &mut Heap) -> Result<Chunk, Vec<RoxError>> {
+    compile_script(source, heap, false)
+}
+
+//Compiles a line entered in the REPL, where a top level expression statement prints its value
+pub fn compile_repl(source: &str, heap: &mut Heap) -> Result<Chunk, Vec<RoxError>> {
+    compile_script(source, heap, true)
+}
+
+//Compiles a single expression into a script that returns its value, for inspecting it
+pub fn compile_expression(source: &str, heap: &mut Heap) -> Result<Chunk, Vec<RoxError>> {
+    let mut scanner = Scanner::new(source);
+    let mut parser = Parser::new(heap);
+    let mut compiler = Compiler::new(FunctionType::Script);
+
+    advance(&mut parser, &mut scanner);
+    expression(&mut parser, &mut scanner, &mut compiler);
+    consume(&mut parser, &mut scanner, EOF, "Expect end of expression.").unwrap_or_else(|e| {
+        parser.handle_error(e);
+    });
+    parser.emit_byte(OpCode::Return as u8);
+
+    if parser.errors.is_empty() {
+        Ok(parser.current_chunk)
+    } else {
+        Err(parser.errors)
+    }
+}
+
+fn compile_script(
+    source: &str,
+    heap: &mut Heap,
+    print_expressions: bool,
+) -> Result<Chunk, Vec<RoxError>> {
     let mut scanner = Scanner::new(source);
-    let mut parser = Parser::new();
-    let mut compiler = Compiler::new();
+    let mut parser = Parser::new(heap);
+    parser.print_expressions = print_expressions;
+    let mut compiler = Compiler::new(FunctionType::Script);
 
     advance(&mut parser, &mut scanner);
 
@@ -455,13 +716,21 @@ pub fn compile(source: &str) -> Option<Chunk> {
         declaration(&mut parser, &mut scanner, &mut compiler);
     }
 
-    parser.end_compiler();
+    parser.end_compiler(&compiler);
 
-    return if !parser.had_error {
-        Some(parser.current_chunk)
+    if parser.errors.is_empty() {
+        Ok(parser.current_chunk)
     } else {
-        None
-    };
+        Err(parser.errors)
+    }
+}
+
+fn synthetic_token(lexeme: &str) -> Token {
+    Token {
+        token_type: Identifier,
+        lexeme: lexeme.to_string(),
+        span: Span::default(),
+    }
 }
 
 fn advance(parser: &mut Parser, scanner: &mut Scanner) {
@@ -496,8 +765,106 @@ fn block(parser: &mut Parser, scanner: &mut Scanner, compiler: &mut Compiler) {
     });
 }
 
+fn function(
+    parser: &mut Parser,
+    scanner: &mut Scanner,
+    compiler: &mut Compiler,
+    function_type: FunctionType,
+) {
+    //The function's compiler takes over, keeping the enclosing one around to resolve upvalues
+    let enclosing = std::mem::replace(compiler, Compiler::new(function_type));
+    compiler.enclosing = Some(Box::new(enclosing));
+    compiler.name = Some(parser.previous.lexeme.clone());
+
+    //Each function gets its own chunk, the enclosing one is restored once the body is compiled
+    let enclosing_chunk = std::mem::take(&mut parser.current_chunk);
+
+    compiler.begin_scope();
+
+    consume(
+        parser,
+        scanner,
+        LeftParen,
+        "Expect '(' after function name.",
+    )
+    .unwrap_or_else(|e| {
+        parser.handle_error(e);
+    });
+
+    if !parser.check(RightParen) {
+        loop {
+            compiler.arity += 1;
+            if compiler.arity > u8::MAX as usize {
+                parser.handle_error(RoxError::new(
+                    "Cannot have more than 255 parameters.",
+                    parser.current.lexeme.clone(),
+                    parser.current.span,
+                ));
+            }
+
+            let parameter = parser.parse_variable(scanner, compiler, "Expect parameter name.");
+            parser.define_variable(compiler, parameter);
+
+            if !match_token(parser, scanner, Comma) {
+                break;
+            }
+        }
+    }
+
+    consume(parser, scanner, RightParen, "Expect ')' after parameters.").unwrap_or_else(|e| {
+        parser.handle_error(e);
+    });
+    consume(
+        parser,
+        scanner,
+        LeftBrace,
+        "Expect '{' before function body.",
+    )
+    .unwrap_or_else(|e| {
+        parser.handle_error(e);
+    });
+    block(parser, scanner, compiler);
+
+    parser.end_compiler(compiler);
+
+    let enclosing = compiler.enclosing.take().unwrap();
+    let function_compiler = std::mem::replace(compiler, *enclosing);
+
+    let chunk = std::mem::replace(&mut parser.current_chunk, enclosing_chunk);
+    let mut function = Function::new(function_compiler.name, function_compiler.arity, chunk);
+    function.upvalue_count = function_compiler.upvalues.len();
+
+    let function = parser.heap.alloc(function);
+    let constant = parser.make_constant(Value::Object(ObjectType::Function(function)));
+    parser.emit_operand(OpCode::Closure, constant);
+
+    for upvalue in function_compiler.upvalues {
+        parser.emit_bytes(upvalue.is_local as u8, upvalue.index);
+    }
+}
+
+fn method(parser: &mut Parser, scanner: &mut Scanner, compiler: &mut Compiler) {
+    consume(parser, scanner, Identifier, "Expect method name.").unwrap_or_else(|e| {
+        parser.handle_error(e);
+    });
+    let constant = parser.identifier_constant(parser.previous.clone());
+
+    let function_type = if parser.previous.lexeme == "init" {
+        FunctionType::Initializer
+    } else {
+        FunctionType::Method
+    };
+    function(parser, scanner, compiler, function_type);
+
+    parser.emit_operand(OpCode::Method, constant);
+}
+
 fn declaration(parser: &mut Parser, scanner: &mut Scanner, compiler: &mut Compiler) {
-    if match_token(parser, scanner, Var) {
+    if match_token(parser, scanner, Class) {
+        class_declaration(parser, scanner, compiler);
+    } else if match_token(parser, scanner, Fun) {
+        fun_declaration(parser, scanner, compiler);
+    } else if match_token(parser, scanner, Var) {
         var_declaration(parser, scanner, compiler);
     } else {
         statement(parser, scanner, compiler);
@@ -508,6 +875,87 @@ fn declaration(parser: &mut Parser, scanner: &mut Scanner, compiler: &mut Compil
     }
 }
 
+fn class_declaration(parser: &mut Parser, scanner: &mut Scanner, compiler: &mut Compiler) {
+    consume(parser, scanner, Identifier, "Expect class name.").unwrap_or_else(|e| {
+        parser.handle_error(e);
+    });
+    let class_name = parser.previous.clone();
+    let name_constant = parser.identifier_constant(class_name.clone());
+
+    compiler.declare_variable(parser).unwrap_or_else(|e| {
+        parser.handle_error(e);
+    });
+
+    parser.emit_operand(OpCode::Class, name_constant);
+
+    let global = if compiler.scope_depth > Depth::Global {
+        None
+    } else {
+        Some(name_constant)
+    };
+    parser.define_variable(compiler, global);
+
+    parser.classes.push(ClassCompiler {
+        has_superclass: false,
+    });
+
+    if match_token(parser, scanner, Less) {
+        consume(parser, scanner, Identifier, "Expect superclass name.").unwrap_or_else(|e| {
+            parser.handle_error(e);
+        });
+        parser.variable(scanner, compiler, false);
+
+        if class_name.lexeme == parser.previous.lexeme {
+            parser.handle_error(RoxError::new(
+                "A class cannot inherit from itself.",
+                parser.previous.lexeme.clone(),
+                parser.previous.span,
+            ));
+        }
+
+        //The superclass is stored in a local named 'super' so methods can capture it
+        compiler.begin_scope();
+        compiler
+            .add_local(synthetic_token("super"))
+            .unwrap_or_else(|e| {
+                parser.handle_error(e);
+            });
+        parser.define_variable(compiler, None);
+
+        parser.named_variable(scanner, compiler, class_name.clone(), false);
+        parser.emit_byte(OpCode::Inherit as u8);
+        parser.classes.last_mut().unwrap().has_superclass = true;
+    }
+
+    //Load the class back onto the stack so methods can be bound to it
+    parser.named_variable(scanner, compiler, class_name, false);
+
+    consume(parser, scanner, LeftBrace, "Expect '{' before class body.").unwrap_or_else(|e| {
+        parser.handle_error(e);
+    });
+    while !parser.check(RightBrace) && !parser.check(EOF) {
+        method(parser, scanner, compiler);
+    }
+    consume(parser, scanner, RightBrace, "Expect '}' after class body.").unwrap_or_else(|e| {
+        parser.handle_error(e);
+    });
+    parser.emit_byte(OpCode::Pop as u8);
+
+    if parser.classes.pop().unwrap().has_superclass {
+        compiler.end_scope(parser);
+    }
+}
+
+fn fun_declaration(parser: &mut Parser, scanner: &mut Scanner, compiler: &mut Compiler) {
+    let global = parser.parse_variable(scanner, compiler, "Expect function name.");
+
+    //A function can refer to itself in its body, so it is initialised before being compiled
+    compiler.mark_initialised();
+    function(parser, scanner, compiler, FunctionType::Function);
+
+    parser.define_variable(compiler, global);
+}
+
 fn var_declaration(parser: &mut Parser, scanner: &mut Scanner, compiler: &mut Compiler) {
     let global = parser.parse_variable(scanner, compiler, "Expect variable name.");
 
@@ -547,6 +995,10 @@ fn statement(parser: &mut Parser, scanner: &mut Scanner, compiler: &mut Compiler
             advance(parser, scanner);
             for_statement(parser, scanner, compiler);
         }
+        Return => {
+            advance(parser, scanner);
+            return_statement(parser, scanner, compiler);
+        }
         LeftBrace => {
             advance(parser, scanner);
 
@@ -571,7 +1023,42 @@ fn expression_statement(parser: &mut Parser, scanner: &mut Scanner, compiler: &m
     consume(parser, scanner, Semicolon, "Expect ';' after expression.").unwrap_or_else(|e| {
         parser.handle_error(e);
     });
-    parser.emit_byte(OpCode::Pop as u8);
+
+    let top_level =
+        compiler.function_type == FunctionType::Script && compiler.scope_depth == Depth::Global;
+    if parser.print_expressions && top_level {
+        parser.emit_byte(OpCode::Print as u8);
+    } else {
+        parser.emit_byte(OpCode::Pop as u8);
+    }
+}
+
+fn return_statement(parser: &mut Parser, scanner: &mut Scanner, compiler: &mut Compiler) {
+    if compiler.function_type == FunctionType::Script {
+        parser.handle_error(RoxError::new(
+            "Cannot return from top-level code.",
+            parser.previous.lexeme.clone(),
+            parser.previous.span,
+        ));
+    }
+
+    if match_token(parser, scanner, Semicolon) {
+        parser.emit_return(compiler);
+    } else {
+        if compiler.function_type == FunctionType::Initializer {
+            parser.handle_error(RoxError::new(
+                "Cannot return a value from an initializer.",
+                parser.previous.lexeme.clone(),
+                parser.previous.span,
+            ));
+        }
+
+        expression(parser, scanner, compiler);
+        consume(parser, scanner, Semicolon, "Expect ';' after return value.").unwrap_or_else(|e| {
+            parser.handle_error(e);
+        });
+        parser.emit_byte(OpCode::Return as u8);
+    }
 }
 
 fn if_statement(parser: &mut Parser, scanner: &mut Scanner, compiler: &mut Compiler) {
@@ -689,14 +1176,11 @@ fn for_statement(parser: &mut Parser, scanner: &mut Scanner, compiler: &mut Comp
         parser.handle_error(e);
     });
 
-    match exit_jump {
-        Some(jump) => {
-            parser.patch_jump(jump).unwrap_or_else(|e| {
-                parser.handle_error(e);
-            });
-            parser.emit_byte(OpCode::Pop as u8); //Condition
-        }
-        None => (),
+    if let Some(jump) = exit_jump {
+        parser.patch_jump(jump).unwrap_or_else(|e| {
+            parser.handle_error(e);
+        });
+        parser.emit_byte(OpCode::Pop as u8); //Condition
     }
 
     compiler.end_scope(parser);
@@ -712,17 +1196,17 @@ fn consume(
         advance(parser, scanner);
         Ok(())
     } else {
-        Err(RoxError::new(message, scanner.get_token(), scanner.line))
+        Err(scanner.error(message))
     }
 }
 
 fn match_token(parser: &mut Parser, scanner: &mut Scanner, token_type: TokenType) -> bool {
-    return if !parser.check(token_type) {
+    if !parser.check(token_type) {
         false
     } else {
         advance(parser, scanner);
         true
-    };
+    }
 }
 
 fn synchronise(parser: &mut Parser, scanner: &mut Scanner) {
@@ -787,12 +1271,12 @@ fn get_rule(token_type: TokenType) -> &'static ParseRule {
     &RULES[token_type as usize]
 }
 
-const RULES: &'static [ParseRule] = &[
+const RULES: &[ParseRule] = &[
     //LeftParen
     ParseRule {
         prefix: Some(|p, s, c, _ca| p.grouping(s, c)),
-        infix: None,
-        precedence: Precedence::None,
+        infix: Some(|p, s, c, _ca| p.call(s, c)),
+        precedence: Precedence::Call,
     },
     //RightParen
     ParseRule {
@@ -821,8 +1305,8 @@ const RULES: &'static [ParseRule] = &[
     //Dot
     ParseRule {
         prefix: None,
-        infix: None,
-        precedence: Precedence::None,
+        infix: Some(|p, s, c, ca| p.dot(s, c, ca)),
+        precedence: Precedence::Call,
     },
     //Minus
     ParseRule {
@@ -988,13 +1472,13 @@ const RULES: &'static [ParseRule] = &[
     },
     //Super
     ParseRule {
-        prefix: None,
+        prefix: Some(|p, s, c, _ca| p.super_(s, c)),
         infix: None,
         precedence: Precedence::None,
     },
     //This
     ParseRule {
-        prefix: None,
+        prefix: Some(|p, s, c, _ca| p.this(s, c)),
         infix: None,
         precedence: Precedence::None,
     },
@@ -1031,7 +1515,8 @@ mod tests {
 
     #[test]
     fn compiler_advance() {
-        let mut parser = Parser::new();
+        let mut heap = Heap::new();
+        let mut parser = Parser::new(&mut heap);
         let mut scanner = Scanner::new("1");
 
         advance(&mut parser, &mut scanner);
@@ -1041,18 +1526,20 @@ mod tests {
 
     #[test]
     fn compiler_advance_with_error() {
-        let mut parser = Parser::new();
+        let mut heap = Heap::new();
+        let mut parser = Parser::new(&mut heap);
         let mut scanner = Scanner::new("\"Hello World");
 
         advance(&mut parser, &mut scanner);
 
-        assert!(parser.had_error);
+        assert_eq!(parser.errors.len(), 1);
         assert!(parser.panic_mode);
     }
 
     #[test]
     fn compiler_consume() {
-        let mut parser = Parser::new();
+        let mut heap = Heap::new();
+        let mut parser = Parser::new(&mut heap);
         let mut scanner = Scanner::new("");
         parser.current = Token::new(&scanner, EOF);
 
@@ -1063,7 +1550,8 @@ mod tests {
 
     #[test]
     fn compiler_consume_error() {
-        let mut parser = Parser::new();
+        let mut heap = Heap::new();
+        let mut parser = Parser::new(&mut heap);
         let mut scanner = Scanner::new("");
         parser.current = Token::new(&scanner, Number);
 
@@ -1074,18 +1562,34 @@ mod tests {
 
     #[test]
     fn compiler_compile() {
-        let result = compile("1 + 1;");
+        let result = compile("1 + 1;", &mut Heap::new());
 
-        assert!(result.is_some());
+        assert!(result.is_ok());
     }
 
     #[test]
     fn compiler_compile_with_error() {
-        let result = compile("(-1");
-        assert!(result.is_none());
+        let result = compile("(-1", &mut Heap::new());
+        assert!(result.is_err());
 
-        let result = compile("1 +");
-        assert!(result.is_none());
+        let result = compile("1 +", &mut Heap::new());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn compiler_compile_collects_every_error() {
+        let result = compile("var 1;\nprint;\nvar a = 1;", &mut Heap::new());
+
+        let errors = result.unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error at '1': Expect variable name."
+        );
+        assert_eq!(
+            errors[1].to_string(),
+            "[line 2] Error at ';': Expect expression."
+        );
     }
 
     #[test]
@@ -1110,7 +1614,8 @@ mod tests {
 
     #[test]
     fn parser_emit_byte() {
-        let mut parser = Parser::new();
+        let mut heap = Heap::new();
+        let mut parser = Parser::new(&mut heap);
 
         parser.emit_byte(0);
 
@@ -1119,7 +1624,8 @@ mod tests {
 
     #[test]
     fn parser_emit_byte2() {
-        let mut parser = Parser::new();
+        let mut heap = Heap::new();
+        let mut parser = Parser::new(&mut heap);
 
         parser.emit_bytes(0, 1);
 
@@ -1129,7 +1635,8 @@ mod tests {
 
     #[test]
     fn parser_make_constant() {
-        let mut parser = Parser::new();
+        let mut heap = Heap::new();
+        let mut parser = Parser::new(&mut heap);
 
         let result = parser.make_constant(Value::Number(1.0));
 
@@ -1138,17 +1645,45 @@ mod tests {
 
     #[test]
     fn parser_make_constant_max_num() {
-        let mut parser = Parser::new();
-        parser.current_chunk.constants = vec![Value::Number(0.0); std::u8::MAX as usize + 1];
+        let mut heap = Heap::new();
+        let mut parser = Parser::new(&mut heap);
+        parser.current_chunk.constants = vec![Value::Number(0.0); MAX_LONG_OPERAND + 1];
 
         parser.make_constant(Value::Number(1.0));
 
-        assert!(parser.had_error);
+        assert_eq!(parser.errors.len(), 1);
+    }
+
+    #[test]
+    fn parser_emit_constant_long() {
+        let mut heap = Heap::new();
+        let mut parser = Parser::new(&mut heap);
+        parser.current_chunk.constants = vec![Value::Number(0.0); 0x012345];
+
+        parser.emit_constant(Value::Number(1.0));
+
+        assert!(parser.errors.is_empty());
+        assert_eq!(
+            parser.current_chunk.code,
+            vec![OpCode::ConstantLong as u8, 0x01, 0x23, 0x45]
+        );
+    }
+
+    #[test]
+    fn parser_emit_operand_without_long_form() {
+        let mut heap = Heap::new();
+        let mut parser = Parser::new(&mut heap);
+
+        parser.emit_operand(OpCode::GetLocal, u8::MAX as usize + 1);
+
+        assert_eq!(parser.errors.len(), 1);
+        assert!(parser.current_chunk.code.is_empty());
     }
 
     #[test]
     fn parser_emit_constant() {
-        let mut parser = Parser::new();
+        let mut heap = Heap::new();
+        let mut parser = Parser::new(&mut heap);
 
         parser.emit_constant(Value::Number(1.0));
 
@@ -1158,17 +1693,20 @@ mod tests {
 
     #[test]
     fn parser_end_compiler() {
-        let mut parser = Parser::new();
+        let mut heap = Heap::new();
+        let mut parser = Parser::new(&mut heap);
 
-        parser.end_compiler();
+        parser.end_compiler(&Compiler::new(FunctionType::Script));
 
-        assert_eq!(parser.current_chunk.code[0], OpCode::Return as u8);
+        assert_eq!(parser.current_chunk.code[0], OpCode::Nil as u8);
+        assert_eq!(parser.current_chunk.code[1], OpCode::Return as u8);
     }
 
     #[test]
     fn parser_emit_loop_max_num() {
-        let mut parser = Parser::new();
-        parser.current_chunk.code = vec![0; std::u16::MAX as usize];
+        let mut heap = Heap::new();
+        let mut parser = Parser::new(&mut heap);
+        parser.current_chunk.code = vec![0; u16::MAX as usize];
         let loop_start = 0;
 
         let result = parser.emit_loop(loop_start);
@@ -1178,8 +1716,9 @@ mod tests {
 
     #[test]
     fn parser_patch_jump_max_num() {
-        let mut parser = Parser::new();
-        parser.current_chunk.code = vec![0; std::u16::MAX as usize + 3];
+        let mut heap = Heap::new();
+        let mut parser = Parser::new(&mut heap);
+        parser.current_chunk.code = vec![0; u16::MAX as usize + 3];
         let offset = 0;
 
         let result = parser.patch_jump(offset);
@@ -1189,17 +1728,108 @@ mod tests {
 
     #[test]
     fn compiler_add_local_max_num() {
-        let mut compiler = Compiler::new();
+        let mut compiler = Compiler::new(FunctionType::Script);
         compiler.locals = vec![
             Local {
                 name: Token::default(),
                 depth: Depth::Uninitialised,
+                is_captured: false,
             };
-            std::u8::MAX as usize + 1
+            u8::MAX as usize + 1
         ];
 
         let result = compiler.add_local(Token::default());
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn compiler_resolve_upvalue() {
+        let name = Token {
+            token_type: Identifier,
+            lexeme: "a".to_string(),
+            span: Span::default(),
+        };
+        let mut enclosing = Compiler::new(FunctionType::Script);
+        enclosing.begin_scope();
+        enclosing.add_local(name.clone()).unwrap();
+        enclosing.mark_initialised();
+        let mut compiler = Compiler::new(FunctionType::Function);
+        compiler.enclosing = Some(Box::new(enclosing));
+
+        let result = compiler.resolve_upvalue(&name).unwrap();
+
+        assert_eq!(result, Some(0));
+        assert!(compiler.upvalues[0].is_local);
+        assert!(compiler.enclosing.unwrap().locals[1].is_captured);
+    }
+
+    #[test]
+    fn compiler_resolve_upvalue_reuses_existing() {
+        let name = Token {
+            token_type: Identifier,
+            lexeme: "a".to_string(),
+            span: Span::default(),
+        };
+        let mut enclosing = Compiler::new(FunctionType::Script);
+        enclosing.begin_scope();
+        enclosing.add_local(name.clone()).unwrap();
+        enclosing.mark_initialised();
+        let mut compiler = Compiler::new(FunctionType::Function);
+        compiler.enclosing = Some(Box::new(enclosing));
+
+        compiler.resolve_upvalue(&name).unwrap();
+        let result = compiler.resolve_upvalue(&name).unwrap();
+
+        assert_eq!(result, Some(0));
+        assert_eq!(compiler.upvalues.len(), 1);
+    }
+
+    #[test]
+    fn compiler_resolve_upvalue_without_enclosing() {
+        let mut compiler = Compiler::new(FunctionType::Script);
+
+        let result = compiler.resolve_upvalue(&Token::default()).unwrap();
+
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn compiler_compile_repl_prints_expressions() {
+        let mut heap = Heap::new();
+
+        let chunk = compile_repl("1; { 2; }", &mut heap).unwrap();
+
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::Constant as u8,
+                0,
+                OpCode::Print as u8,
+                OpCode::Constant as u8,
+                1,
+                OpCode::Pop as u8,
+                OpCode::Nil as u8,
+                OpCode::Return as u8,
+            ]
+        );
+    }
+
+    #[test]
+    fn compiler_compile_expression() {
+        let mut heap = Heap::new();
+
+        let chunk = compile_expression("nil", &mut heap).unwrap();
+
+        assert_eq!(chunk.code, vec![OpCode::Nil as u8, OpCode::Return as u8]);
+    }
+
+    #[test]
+    fn compiler_compile_expression_rejects_statements() {
+        let mut heap = Heap::new();
+
+        let result = compile_expression("1; 2", &mut heap);
+
+        assert_eq!(result.unwrap_err()[0].message, "Expect end of expression.");
+    }
 }
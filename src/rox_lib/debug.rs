@@ -1,64 +1,404 @@
-use crate::chunk::OpCode::*;
-use crate::chunk::{Chunk, OpCode};
+use crate::chunk::{Chunk, DecodeError, OpCode, Operand};
+use crate::heap::Heap;
+use crate::object::ObjectType;
+use crate::value::Value;
+use std::convert::TryFrom;
+use std::io;
+use std::io::Write;
 
-pub fn disassemble_chuck(chunk: &Chunk, name: &str) {
-    println!("== {} ==", name);
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub offset: usize,
+    pub line: i32,
+    pub opcode: OpCode,
+    pub operands: Operands,
+}
+
+impl Instruction {
+    //Bytes taken by the opcode and its operands
+    pub fn length(&self) -> usize {
+        1 + match &self.operands {
+            Operands::None => 0,
+            Operands::Byte(_) => 1,
+            Operands::Constant(_) => self.opcode.operand().width(),
+            Operands::Jump { .. } => 2,
+            Operands::Closure { upvalues, .. } => {
+                self.opcode.operand().width() + upvalues.len() * 2
+            }
+        }
+    }
+}
+
+//Decoded operands, see Operand for how each kind is encoded
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operands {
+    None,
+    Byte(u8),
+    Constant(usize),
+    //Target is the offset execution continues from when the jump is taken
+    Jump {
+        jump: u16,
+        target: isize,
+    },
+    Closure {
+        constant: usize,
+        upvalues: Vec<Capture>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capture {
+    pub is_local: bool,
+    pub index: u8,
+}
+
+//Decodes the instructions of a chunk in order. An unknown opcode is skipped over after being
+//reported, a truncated or otherwise malformed instruction ends the iteration
+pub fn instructions<'a>(chunk: &'a Chunk, heap: &'a Heap) -> Instructions<'a> {
+    Instructions {
+        chunk,
+        heap,
+        offset: 0,
+    }
+}
+
+pub struct Instructions<'a> {
+    chunk: &'a Chunk,
+    heap: &'a Heap,
+    offset: usize,
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Result<Instruction, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.chunk.code.len() {
+            return None;
+        }
+
+        let result = decode_instruction(self.chunk, self.heap, self.offset);
+        self.offset = match &result {
+            Ok(instruction) => self.offset + instruction.length(),
+            Err(DecodeError::UnknownOpcode(_)) => self.offset + 1,
+            Err(_) => self.chunk.code.len(),
+        };
+        Some(result)
+    }
+}
+
+pub fn decode_instruction(
+    chunk: &Chunk,
+    heap: &Heap,
+    offset: usize,
+) -> Result<Instruction, DecodeError> {
+    let code = &chunk.code;
+    let opcode = OpCode::try_from(code[offset])?;
+
+    let operand = opcode.operand();
+    if offset + operand.width() >= code.len() && operand != Operand::None {
+        return Err(DecodeError::Truncated(opcode));
+    }
+    let byte = |n: usize| code[offset + n] as usize;
+
+    let operands = match operand {
+        Operand::None => Operands::None,
+        Operand::Byte => Operands::Byte(code[offset + 1]),
+        Operand::Constant => Operands::Constant(byte(1)),
+        Operand::ConstantLong => Operands::Constant(byte(1) << 16 | byte(2) << 8 | byte(3)),
+        Operand::Jump | Operand::Loop => {
+            let jump = u16::from_be_bytes([code[offset + 1], code[offset + 2]]);
+            let sign = if operand == Operand::Loop { -1 } else { 1 };
+            Operands::Jump {
+                jump,
+                target: offset as isize + 3 + sign * jump as isize,
+            }
+        }
+        Operand::Closure | Operand::ClosureLong => {
+            let constant = if operand == Operand::Closure {
+                byte(1)
+            } else {
+                byte(1) << 16 | byte(2) << 8 | byte(3)
+            };
+            //The upvalue pairs start after the constant
+            let start = offset + 1 + operand.width();
+            let upvalue_count = match chunk.constants.get(constant) {
+                Some(Value::Object(ObjectType::Function(f))) => heap.get(*f).upvalue_count,
+                _ => 0,
+            };
+            if start + upvalue_count * 2 > code.len() {
+                return Err(DecodeError::Truncated(opcode));
+            }
+
+            let upvalues = (0..upvalue_count)
+                .map(|i| {
+                    let is_local = match code[start + i * 2] {
+                        0 => false,
+                        1 => true,
+                        flag => return Err(DecodeError::InvalidCapture(flag)),
+                    };
+                    Ok(Capture {
+                        is_local,
+                        index: code[start + 1 + i * 2],
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+            Operands::Closure { constant, upvalues }
+        }
+    };
+
+    Ok(Instruction {
+        offset,
+        line: chunk.get_line(offset),
+        opcode,
+        operands,
+    })
+}
+
+pub fn disassemble_chuck<W: Write>(
+    out: &mut W,
+    chunk: &Chunk,
+    heap: &Heap,
+    name: &str,
+) -> io::Result<()> {
+    writeln!(out, "== {} ==", name)?;
 
     let mut offset = 0;
     while offset < chunk.code.len() {
-        offset = disassemble_instruction(chunk, offset);
+        offset = disassemble_instruction(out, chunk, heap, offset)?;
     }
+    Ok(())
+}
+
+//Writes a script chunk preceded by every function nested in it, innermost first
+pub fn disassemble_program<W: Write>(out: &mut W, chunk: &Chunk, heap: &Heap) -> io::Result<()> {
+    disassemble_nested(out, chunk, heap, "<script>")
+}
+
+fn disassemble_nested<W: Write>(
+    out: &mut W,
+    chunk: &Chunk,
+    heap: &Heap,
+    name: &str,
+) -> io::Result<()> {
+    for constant in &chunk.constants {
+        if let Value::Object(ObjectType::Function(f)) = constant {
+            let function = heap.get(*f);
+            let name = function.name.as_deref().unwrap_or("<script>");
+            disassemble_nested(out, &function.chunk, heap, name)?;
+        }
+    }
+
+    disassemble_chuck(out, chunk, heap, name)
 }
 
-pub fn disassemble_instruction(chunk: &Chunk, offset: usize) -> usize {
-    print!("{:0>4} ", offset);
+//Writes the instruction at offset, returning the offset of the next one
+pub fn disassemble_instruction<W: Write>(
+    out: &mut W,
+    chunk: &Chunk,
+    heap: &Heap,
+    offset: usize,
+) -> io::Result<usize> {
+    write!(out, "{:0>4} ", offset)?;
 
-    if offset > 0 && chunk.lines[offset] == chunk.lines[offset - 1] {
-        print!("   | ");
+    let line = chunk.get_line(offset);
+    if offset > 0 && line == chunk.get_line(offset - 1) {
+        write!(out, "   | ")?;
     } else {
-        print!("{:>4} ", chunk.lines[offset]);
+        write!(out, "{:>4} ", line)?;
     }
 
-    let instruction = OpCode::from(chunk.code[offset]);
-    match instruction {
-        Constant | GetGlobal | DefineGlobal | SetGlobal => {
-            constant_instruction(instruction, chunk, offset)
+    match decode_instruction(chunk, heap, offset) {
+        Ok(instruction) => {
+            write_instruction(out, chunk, heap, &instruction)?;
+            Ok(offset + instruction.length())
+        }
+        Err(error) => {
+            writeln!(out, "{}", error)?;
+            Ok(match error {
+                DecodeError::UnknownOpcode(_) => offset + 1,
+                _ => chunk.code.len(),
+            })
         }
-        GetLocal | SetLocal => byte_instruction(instruction, chunk, offset),
-        Nil | True | False | Pop | Equal | Greater | Less | Add | Subtract | Multiple | Divide
-        | Not | Negate | Print | Return => simple_instruction(instruction, offset),
-        Jump | JumpIfFalse => jump_instruction(instruction, 1, chunk, offset),
-        Loop => jump_instruction(instruction, -1, chunk, offset),
     }
 }
 
-fn simple_instruction(instruction: OpCode, offset: usize) -> usize {
-    println!("{}", instruction);
-    offset + 1
-}
+//Text form of a decoded instruction, without the offset and line prefix
+pub fn write_instruction<W: Write>(
+    out: &mut W,
+    chunk: &Chunk,
+    heap: &Heap,
+    instruction: &Instruction,
+) -> io::Result<()> {
+    let opcode = instruction.opcode;
 
-fn constant_instruction(instruction: OpCode, chunk: &Chunk, offset: usize) -> usize {
-    let constant = chunk.code[offset + 1] as usize;
-    println!(
-        "{:<16} {:>4} {}",
-        instruction, constant, chunk.constants[constant]
-    );
-    offset + 2
+    match &instruction.operands {
+        Operands::None => writeln!(out, "{}", opcode),
+        Operands::Byte(slot) => writeln!(out, "{:<16} {:>4}", opcode, slot),
+        Operands::Constant(constant) => {
+            write!(out, "{:<16} {:>4} ", opcode, constant)?;
+            write_constant(out, chunk, heap, *constant)
+        }
+        Operands::Jump { target, .. } => writeln!(
+            out,
+            "{:<16} {:>4} -> {}",
+            opcode, instruction.offset, target
+        ),
+        Operands::Closure { constant, upvalues } => {
+            write!(out, "{:<16} {:>4} ", opcode, constant)?;
+            write_constant(out, chunk, heap, *constant)?;
+
+            for (i, upvalue) in upvalues.iter().enumerate() {
+                writeln!(
+                    out,
+                    "{:0>4}    |                     {} {}",
+                    instruction.offset + 1 + opcode.operand().width() + i * 2,
+                    if upvalue.is_local { "local" } else { "upvalue" },
+                    upvalue.index
+                )?;
+            }
+            Ok(())
+        }
+    }
 }
 
-fn byte_instruction(instruction: OpCode, chunk: &Chunk, offset: usize) -> usize {
-    let slot = chunk.code[offset + 1] as usize;
-    println!("{:<16} {:>4}", instruction, slot);
-    offset + 2
+fn write_constant<W: Write>(
+    out: &mut W,
+    chunk: &Chunk,
+    heap: &Heap,
+    constant: usize,
+) -> io::Result<()> {
+    match chunk.constants.get(constant) {
+        Some(value) => writeln!(out, "{}", value.display(heap)),
+        None => writeln!(out, "<invalid constant>"),
+    }
 }
 
-fn jump_instruction(instruction: OpCode, sign: i32, chunk: &Chunk, offset: usize) -> usize {
-    let jump = u16::from_be_bytes([chunk.code[offset + 1], chunk.code[offset + 2]]) as i32;
-    println!(
-        "{:<16} {:>4} -> {}",
-        instruction,
-        offset,
-        offset as i32 + 3 + (sign * jump)
-    );
-    offset + 3
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+    use crate::object::Function;
+
+    fn disassemble(source: &str) -> String {
+        let mut heap = Heap::new();
+        let chunk = compile(source, &mut heap).unwrap();
+        let mut out = Vec::new();
+
+        disassemble_chuck(&mut out, &chunk, &heap, "test").unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn debug_disassemble_chunk() {
+        let result = disassemble("var a = 1;\nif (a) print a + 2;\n");
+
+        assert_eq!(
+            result,
+            "== test ==
+0000    1 CONSTANT            1 1
+0002    | DEFINE GLOBAL       0 a
+0004    2 GET GLOBAL          2 a
+0006    | JUMP IF FALSE       6 -> 19
+0009    | POP
+0010    | GET GLOBAL          3 a
+0012    | CONSTANT            4 2
+0014    | ADD
+0015    | PRINT
+0016    | JUMP               16 -> 20
+0019    | POP
+0020    3 NIL
+0021    | RETURN
+"
+        );
+    }
+
+    #[test]
+    fn debug_disassemble_closure() {
+        let mut heap = Heap::new();
+        let mut function = Function::new(Some("g".to_string()), 0, Chunk::new());
+        function.upvalue_count = 2;
+        let function = heap.alloc(function);
+
+        let mut chunk = Chunk::new();
+        chunk.add_constant(Value::Object(ObjectType::Function(function)));
+        for byte in [OpCode::Closure as u8, 0, 1, 1, 0, 3, OpCode::Return as u8] {
+            chunk.write(byte, 1);
+        }
+        let mut out = Vec::new();
+
+        disassemble_chuck(&mut out, &chunk, &heap, "test").unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "== test ==
+0000    1 CLOSURE             0 <fn g>
+0002    |                     local 1
+0004    |                     upvalue 3
+0006    | RETURN
+"
+        );
+    }
+
+    #[test]
+    fn debug_disassemble_program() {
+        let mut heap = Heap::new();
+        let chunk = compile("fun f() { fun g() {} }", &mut heap).unwrap();
+        let mut out = Vec::new();
+
+        disassemble_program(&mut out, &chunk, &heap).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let headers: Vec<&str> = out.lines().filter(|l| l.starts_with("==")).collect();
+        assert_eq!(headers, vec!["== g ==", "== f ==", "== <script> =="]);
+    }
+
+    #[test]
+    fn debug_instructions() {
+        let mut heap = Heap::new();
+        let chunk = compile("var a = 1;\nprint a;", &mut heap).unwrap();
+
+        let result: Vec<Instruction> = instructions(&chunk, &heap).map(Result::unwrap).collect();
+
+        assert_eq!(result.len(), 6);
+        assert_eq!(
+            result[0],
+            Instruction {
+                offset: 0,
+                line: 1,
+                opcode: OpCode::Constant,
+                operands: Operands::Constant(1),
+            }
+        );
+        assert_eq!(result[2].offset, 4);
+        assert_eq!(result[2].line, 2);
+        assert_eq!(result[2].opcode, OpCode::GetGlobal);
+        assert_eq!(result[4].opcode, OpCode::Nil);
+    }
+
+    #[test]
+    fn debug_instructions_unknown_opcode() {
+        let mut chunk = Chunk::new();
+        chunk.write(200, 0);
+        chunk.write(OpCode::Return as u8, 0);
+
+        let heap = Heap::new();
+        let result: Vec<_> = instructions(&chunk, &heap).collect();
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0], Err(DecodeError::UnknownOpcode(200)));
+        assert_eq!(result[1].as_ref().unwrap().opcode, OpCode::Return);
+    }
+
+    #[test]
+    fn debug_instructions_truncated() {
+        let mut chunk = Chunk::new();
+        chunk.write(OpCode::Jump as u8, 0);
+        chunk.write(0, 0);
+
+        let heap = Heap::new();
+        let result: Vec<_> = instructions(&chunk, &heap).collect();
+
+        assert_eq!(result, vec![Err(DecodeError::Truncated(OpCode::Jump))]);
+    }
 }
@@ -0,0 +1,538 @@
+use crate::chunk::LineRun;
+use crate::object::{
+    BoundMethod, Class, Closure, Function, Instance, List, Native, ObjectType, Upvalue,
+};
+use crate::value::Value;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem;
+
+const GC_HEAP_GROW_FACTOR: usize = 2;
+const GC_INITIAL_THRESHOLD: usize = 1024 * 1024;
+
+//Handle to an object owned by a Heap, only valid for the heap that allocated it
+pub struct Gc<T> {
+    index: usize,
+    marker: PhantomData<T>,
+}
+
+impl<T> Gc<T> {
+    fn new(index: usize) -> Self {
+        Gc {
+            index,
+            marker: PhantomData,
+        }
+    }
+}
+
+//Implemented manually since deriving would require T to implement these traits as well
+impl<T> Copy for Gc<T> {}
+
+impl<T> Clone for Gc<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> PartialEq for Gc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Gc<T> {}
+
+impl<T> Hash for Gc<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<T> Debug for Gc<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Gc({})", self.index)
+    }
+}
+
+pub enum HeapObject {
+    String(String),
+    Function(Function),
+    Closure(Closure),
+    Upvalue(Upvalue),
+    Class(Class),
+    Instance(Instance),
+    BoundMethod(BoundMethod),
+    Native(Native),
+    List(List),
+}
+
+impl HeapObject {
+    //Approximate number of bytes owned by the object, used to decide when to collect
+    fn size(&self) -> usize {
+        mem::size_of::<Entry>()
+            + match self {
+                HeapObject::String(s) => s.capacity(),
+                HeapObject::Function(f) => {
+                    f.chunk.code.capacity()
+                        + f.chunk.constants.capacity() * mem::size_of::<Value>()
+                        + f.chunk.lines.capacity() * mem::size_of::<LineRun>()
+                }
+                HeapObject::Closure(c) => c.upvalues.capacity() * mem::size_of::<Gc<Upvalue>>(),
+                HeapObject::Upvalue(_) => 0,
+                HeapObject::Class(c) => {
+                    c.name.capacity()
+                        + c.methods.capacity()
+                            * (mem::size_of::<Gc<String>>() + mem::size_of::<Gc<Closure>>())
+                }
+                HeapObject::Instance(i) => {
+                    i.fields.capacity() * (mem::size_of::<Gc<String>>() + mem::size_of::<Value>())
+                }
+                HeapObject::BoundMethod(_) => 0,
+                HeapObject::Native(n) => n.name.capacity(),
+                HeapObject::List(l) => l.items.capacity() * mem::size_of::<Value>(),
+            }
+    }
+}
+
+pub trait HeapType: Sized {
+    fn from_object(object: &HeapObject) -> &Self;
+    fn from_object_mut(object: &mut HeapObject) -> &mut Self;
+}
+
+//Strings don't implement this, they are only allocated through Heap::intern so equal strings
+//always share a handle
+pub trait Allocate: HeapType {
+    fn into_object(self) -> HeapObject;
+}
+
+macro_rules! heap_type {
+    ($type:ty, $variant:ident) => {
+        impl HeapType for $type {
+            fn from_object(object: &HeapObject) -> &Self {
+                match object {
+                    HeapObject::$variant(o) => o,
+                    _ => panic!("Object is not a {}", stringify!($variant)),
+                }
+            }
+
+            fn from_object_mut(object: &mut HeapObject) -> &mut Self {
+                match object {
+                    HeapObject::$variant(o) => o,
+                    _ => panic!("Object is not a {}", stringify!($variant)),
+                }
+            }
+        }
+    };
+}
+
+macro_rules! allocate {
+    ($type:ty, $variant:ident) => {
+        heap_type!($type, $variant);
+
+        impl Allocate for $type {
+            fn into_object(self) -> HeapObject {
+                HeapObject::$variant(self)
+            }
+        }
+    };
+}
+
+heap_type!(String, String);
+allocate!(Function, Function);
+allocate!(Closure, Closure);
+allocate!(Upvalue, Upvalue);
+allocate!(Class, Class);
+allocate!(Instance, Instance);
+allocate!(BoundMethod, BoundMethod);
+allocate!(Native, Native);
+allocate!(List, List);
+
+struct Entry {
+    object: HeapObject,
+    marked: bool,
+}
+
+pub struct Heap {
+    objects: Vec<Option<Entry>>,
+    //Intern table from the hash of a string to the strings with that hash, so the text itself is
+    //only stored in the heap. It doesn't keep its strings alive
+    strings: HashMap<u64, Vec<Gc<String>>>,
+    hasher: RandomState,
+    free_slots: Vec<usize>,
+    gray_stack: Vec<usize>,
+    bytes_allocated: usize,
+    next_gc: usize,
+    allocations_since_gc: usize,
+    pub stress_gc: bool, //Collect whenever anything was allocated, used to shake out GC bugs
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Heap {
+            objects: Vec::new(),
+            strings: HashMap::new(),
+            hasher: RandomState::new(),
+            free_slots: Vec::new(),
+            gray_stack: Vec::new(),
+            bytes_allocated: 0,
+            next_gc: GC_INITIAL_THRESHOLD,
+            allocations_since_gc: 0,
+            stress_gc: false,
+        }
+    }
+
+    pub fn alloc<T: Allocate>(&mut self, value: T) -> Gc<T> {
+        Gc::new(self.alloc_object(value.into_object()))
+    }
+
+    pub fn intern(&mut self, string: &str) -> Gc<String> {
+        match self.find_string(string) {
+            Some(handle) => handle,
+            None => self.intern_owned(string.to_owned()),
+        }
+    }
+
+    //The interned copy of a string if there is one, without allocating it otherwise
+    pub fn find_string(&self, string: &str) -> Option<Gc<String>> {
+        let candidates = self.strings.get(&self.hasher.hash_one(string))?;
+        candidates
+            .iter()
+            .copied()
+            .find(|handle| self.get(*handle) == string)
+    }
+
+    //Avoids copying strings that were built at runtime, like the result of concatenation
+    pub fn intern_owned(&mut self, string: String) -> Gc<String> {
+        if let Some(handle) = self.find_string(&string) {
+            return handle;
+        }
+
+        let hash = self.hasher.hash_one(&string);
+        let handle = Gc::new(self.alloc_object(HeapObject::String(string)));
+        self.strings.entry(hash).or_default().push(handle);
+        handle
+    }
+
+    fn alloc_object(&mut self, object: HeapObject) -> usize {
+        self.bytes_allocated += object.size();
+        self.allocations_since_gc += 1;
+
+        let entry = Some(Entry {
+            object,
+            marked: false,
+        });
+
+        match self.free_slots.pop() {
+            Some(index) => {
+                self.objects[index] = entry;
+                index
+            }
+            None => {
+                self.objects.push(entry);
+                self.objects.len() - 1
+            }
+        }
+    }
+
+    pub fn get<T: HeapType>(&self, handle: Gc<T>) -> &T {
+        match &self.objects[handle.index] {
+            Some(entry) => T::from_object(&entry.object),
+            None => panic!("Use of freed object {:?}", handle),
+        }
+    }
+
+    pub fn get_mut<T: HeapType>(&mut self, handle: Gc<T>) -> &mut T {
+        match &mut self.objects[handle.index] {
+            Some(entry) => T::from_object_mut(&mut entry.object),
+            None => panic!("Use of freed object {:?}", handle),
+        }
+    }
+
+    pub fn object_count(&self) -> usize {
+        self.objects.len() - self.free_slots.len()
+    }
+
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated
+    }
+
+    pub fn should_collect(&self) -> bool {
+        if self.stress_gc {
+            self.allocations_since_gc > 0
+        } else {
+            self.bytes_allocated > self.next_gc
+        }
+    }
+
+    pub fn mark_value(&mut self, value: Value) {
+        if let Value::Object(object) = value {
+            self.mark_object(object);
+        }
+    }
+
+    pub fn mark_object(&mut self, object: ObjectType) {
+        self.mark_index(object_index(object));
+    }
+
+    pub fn mark<T>(&mut self, handle: Gc<T>) {
+        self.mark_index(handle.index);
+    }
+
+    fn mark_index(&mut self, index: usize) {
+        if let Some(entry) = &mut self.objects[index] {
+            if !entry.marked {
+                entry.marked = true;
+                self.gray_stack.push(index);
+            }
+        }
+    }
+
+    //Traces everything reachable from the marked roots, then frees whatever was not reached
+    pub fn collect(&mut self) {
+        self.trace_references();
+        self.sweep();
+
+        self.next_gc = self.bytes_allocated * GC_HEAP_GROW_FACTOR;
+        self.allocations_since_gc = 0;
+    }
+
+    fn trace_references(&mut self) {
+        while let Some(index) = self.gray_stack.pop() {
+            self.blacken(index);
+        }
+    }
+
+    fn blacken(&mut self, index: usize) {
+        //Taken out of its slot while its references are marked, an object refering to itself is
+        //already marked so it doesn't need to be found again
+        let entry = self.objects[index].take().unwrap();
+
+        match &entry.object {
+            HeapObject::String(_)
+            | HeapObject::Native(_)
+            | HeapObject::Upvalue(Upvalue::Open(_)) => {}
+            HeapObject::Upvalue(Upvalue::Closed(value)) => self.mark_value(*value),
+            HeapObject::List(list) => {
+                for item in &list.items {
+                    self.mark_value(*item);
+                }
+            }
+            HeapObject::Function(function) => {
+                for constant in &function.chunk.constants {
+                    self.mark_value(*constant);
+                }
+            }
+            HeapObject::Closure(closure) => {
+                self.mark(closure.function);
+                for upvalue in &closure.upvalues {
+                    self.mark(*upvalue);
+                }
+            }
+            HeapObject::Class(class) => {
+                for (name, method) in &class.methods {
+                    self.mark(*name);
+                    self.mark(*method);
+                }
+            }
+            HeapObject::Instance(instance) => {
+                self.mark(instance.class);
+                for (name, value) in &instance.fields {
+                    self.mark(*name);
+                    self.mark_value(*value);
+                }
+            }
+            HeapObject::BoundMethod(bound) => {
+                self.mark_value(bound.receiver);
+                self.mark(bound.method);
+            }
+        }
+
+        self.objects[index] = Some(entry);
+    }
+
+    fn sweep(&mut self) {
+        //Unreachable strings are about to be freed, so they can't be handed out again
+        let objects = &self.objects;
+        self.strings.retain(|_, handles| {
+            handles.retain(|handle| match &objects[handle.index] {
+                Some(entry) => entry.marked,
+                None => false,
+            });
+            !handles.is_empty()
+        });
+
+        //Live objects can have grown since they were allocated, so the total is recounted
+        self.bytes_allocated = 0;
+
+        for (index, slot) in self.objects.iter_mut().enumerate() {
+            match slot {
+                Some(entry) if entry.marked => {
+                    entry.marked = false;
+                    self.bytes_allocated += entry.object.size();
+                }
+                Some(_) => {
+                    *slot = None;
+                    self.free_slots.push(index);
+                }
+                None => (),
+            }
+        }
+    }
+}
+
+fn object_index(object: ObjectType) -> usize {
+    match object {
+        ObjectType::String(o) => o.index,
+        ObjectType::Function(o) => o.index,
+        ObjectType::Closure(o) => o.index,
+        ObjectType::Class(o) => o.index,
+        ObjectType::Instance(o) => o.index,
+        ObjectType::BoundMethod(o) => o.index,
+        ObjectType::Native(o) => o.index,
+        ObjectType::List(o) => o.index,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    #[test]
+    fn heap_alloc_and_get() {
+        let mut heap = Heap::new();
+
+        let handle = heap.alloc(Class::new("A".to_string()));
+
+        assert_eq!(heap.get(handle).name, "A");
+        assert_eq!(heap.object_count(), 1);
+    }
+
+    #[test]
+    fn heap_intern_returns_same_handle() {
+        let mut heap = Heap::new();
+
+        let a = heap.intern("hello");
+        let b = heap.intern_owned("hello".to_string());
+
+        assert_eq!(a, b);
+        assert_ne!(a, heap.intern("world"));
+        assert_eq!(heap.get(a), "hello");
+        assert_eq!(heap.object_count(), 2);
+    }
+
+    #[test]
+    fn heap_intern_compares_colliding_strings() {
+        let mut heap = Heap::new();
+        let a = heap.intern("a");
+        let b = heap.intern("b");
+
+        //Pretend "b" has the same hash as "a"
+        let hash = heap.hasher.hash_one("a");
+        heap.strings.get_mut(&hash).unwrap().insert(0, b);
+
+        assert_eq!(heap.find_string("a"), Some(a));
+        assert_eq!(heap.intern("a"), a);
+        assert_eq!(heap.object_count(), 2);
+    }
+
+    #[test]
+    fn heap_collect_removes_unreachable_interned_strings() {
+        let mut heap = Heap::new();
+        heap.intern("garbage");
+        let kept = heap.intern("kept");
+
+        heap.mark(kept);
+        heap.collect();
+
+        assert_eq!(heap.strings.len(), 1);
+        assert_eq!(heap.intern("kept"), kept);
+    }
+
+    #[test]
+    fn heap_collect_frees_unreachable_objects() {
+        let mut heap = Heap::new();
+        heap.alloc(Class::new("garbage".to_string()));
+        let kept = heap.alloc(Class::new("kept".to_string()));
+
+        heap.mark(kept);
+        heap.collect();
+
+        assert_eq!(heap.object_count(), 1);
+        assert_eq!(heap.get(kept).name, "kept");
+    }
+
+    #[test]
+    fn heap_collect_traces_references() {
+        let mut heap = Heap::new();
+        let mut chunk = Chunk::new();
+        let constant = heap.intern("constant");
+        chunk.add_constant(Value::Object(ObjectType::String(constant)));
+        let function = heap.alloc(Function::new(None, 0, chunk));
+        let upvalue = heap.alloc(Upvalue::Closed(Value::Number(1.0)));
+        let closure = heap.alloc(Closure::new(function, vec![upvalue]));
+
+        heap.mark(closure);
+        heap.collect();
+
+        assert_eq!(heap.object_count(), 4);
+        assert_eq!(heap.get(constant), "constant");
+    }
+
+    #[test]
+    fn heap_collect_handles_cycles() {
+        let mut heap = Heap::new();
+        let class = heap.alloc(Class::new("A".to_string()));
+        let instance = heap.alloc(Instance::new(class));
+        let value = Value::Object(ObjectType::Instance(instance));
+        let name = heap.intern("me");
+        heap.get_mut(instance).fields.insert(name, value);
+
+        heap.collect();
+
+        assert_eq!(heap.object_count(), 0);
+    }
+
+    #[test]
+    fn heap_reuses_freed_slots() {
+        let mut heap = Heap::new();
+        let garbage = heap.intern("garbage");
+        heap.collect();
+
+        let handle = heap.intern("new");
+
+        assert_eq!(handle, garbage);
+        assert_eq!(heap.get(handle), "new");
+    }
+
+    #[test]
+    fn heap_should_collect_when_stressed() {
+        let mut heap = Heap::new();
+        heap.stress_gc = true;
+        assert!(!heap.should_collect());
+
+        heap.intern("a");
+
+        assert!(heap.should_collect());
+    }
+
+    #[test]
+    #[should_panic(expected = "Use of freed object")]
+    fn heap_get_freed_object_panics() {
+        let mut heap = Heap::new();
+        let handle = heap.alloc(Class::new("garbage".to_string()));
+        heap.collect();
+
+        heap.get(handle);
+    }
+}
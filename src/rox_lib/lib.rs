@@ -2,29 +2,94 @@ use std::error::Error;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 
+pub mod bytecode;
 pub mod chunk;
 pub mod compiler;
 pub mod debug;
+pub mod heap;
+pub mod native;
 pub mod object;
+pub mod output;
 pub mod scanner;
 pub mod value;
+pub mod verifier;
 pub mod vm;
 
-#[derive(Debug)]
+//Location of a piece of source code, offset and length are in bytes and column counts characters.
+//Line and column are where it starts, even for a string running over several lines
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Span {
+    pub offset: usize,
+    pub length: usize,
+    pub line: i32,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(offset: usize, length: usize, line: i32, column: usize) -> Self {
+        Span {
+            offset,
+            length,
+            line,
+            column,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub struct RoxError {
     pub message: String,
     pub token: String,
-    pub line: i32,
+    pub span: Span,
 }
 
 impl RoxError {
-    pub fn new(message: &str, token: String, line: i32) -> Self {
+    pub fn new(message: &str, token: String, span: Span) -> Self {
         RoxError {
             message: message.to_string(),
             token,
-            line,
+            span,
         }
     }
+
+    pub fn line(&self) -> i32 {
+        self.span.line
+    }
+
+    //Whether the error comes from the source ending too early, such as an unclosed block or
+    //string, so that more input could fix it
+    pub fn is_at_end(&self, source: &str) -> bool {
+        let end = self.span.offset + self.span.length;
+        end >= source.len() && (self.span.length == 0 || self.token.starts_with('"'))
+    }
+
+    //The error followed by the source line it happened on, with the token underlined
+    pub fn render(&self, source: &str) -> String {
+        let offset = self.span.offset.min(source.len());
+        let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[offset..]
+            .find('\n')
+            .map_or(source.len(), |i| offset + i);
+        let text = &source[line_start..line_end];
+
+        //Multi-line tokens are only underlined up to the end of their first line
+        let end = (offset + self.span.length).min(line_end);
+        let indent = source[line_start..offset].chars().count();
+        let width = source[offset..end].chars().count().max(1);
+
+        let line_number = self.span.line;
+        let gutter = " ".repeat(line_number.to_string().len());
+
+        format!(
+            "{}\n{} | {}\n{} | {}{}",
+            self,
+            line_number,
+            text,
+            gutter,
+            " ".repeat(indent),
+            "^".repeat(width)
+        )
+    }
 }
 
 impl Display for RoxError {
@@ -32,9 +97,69 @@ impl Display for RoxError {
         write!(
             f,
             "[line {}] Error at '{}': {}",
-            self.line, self.token, self.message
+            self.span.line, self.token, self.message
         )
     }
 }
 
 impl Error for RoxError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+    use crate::heap::Heap;
+
+    #[test]
+    fn rox_error_render() {
+        let error = RoxError::new("Oops.", "foo".to_string(), Span::new(15, 3, 2, 5));
+
+        let result = error.render("var a = 1;\nvar foo = 2;\n");
+
+        assert_eq!(
+            result,
+            "[line 2] Error at 'foo': Oops.\n2 | var foo = 2;\n  |     ^^^"
+        );
+    }
+
+    #[test]
+    fn rox_error_render_at_end() {
+        let error = RoxError::new("Oops.", "end".to_string(), Span::new(3, 0, 1, 4));
+
+        let result = error.render("1 +");
+
+        assert_eq!(result, "[line 1] Error at 'end': Oops.\n1 | 1 +\n  |    ^");
+    }
+
+    #[test]
+    fn rox_error_render_multiline_token() {
+        let error = RoxError::new("Oops.", "\"a".to_string(), Span::new(2, 4, 2, 1));
+
+        let result = error.render("1\n\"a\nb\"");
+
+        assert_eq!(result, "[line 2] Error at '\"a': Oops.\n2 | \"a\n  | ^^");
+    }
+
+    #[test]
+    fn rox_error_is_at_end() {
+        let source = "{ \"a";
+        let end = RoxError::new("Oops.", "".to_string(), Span::new(4, 0, 1, 5));
+        let string = RoxError::new("Oops.", "\"a".to_string(), Span::new(2, 2, 1, 3));
+        let middle = RoxError::new("Oops.", "{".to_string(), Span::new(0, 1, 1, 1));
+        let empty = RoxError::new("Oops.", "".to_string(), Span::new(1, 0, 1, 2));
+
+        assert!(end.is_at_end(source));
+        assert!(string.is_at_end(source));
+        assert!(!middle.is_at_end(source));
+        assert!(!empty.is_at_end(source));
+    }
+
+    #[test]
+    fn rox_error_is_at_end_of_source() {
+        let source = "{";
+        let errors = compile(source, &mut Heap::new()).unwrap_err();
+
+        assert_eq!(errors[0].span, Span::new(source.len(), 0, 1, 2));
+        assert!(errors[0].is_at_end(source));
+    }
+}
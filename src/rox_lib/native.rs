@@ -0,0 +1,203 @@
+use crate::heap::Heap;
+use crate::object::ObjectType;
+use crate::value::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+//Seconds since the Unix epoch
+pub fn clock(_heap: &mut Heap, _args: &[Value]) -> Result<Value, String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?;
+
+    Ok(Value::Number(now.as_secs_f64()))
+}
+
+pub fn str(heap: &mut Heap, args: &[Value]) -> Result<Value, String> {
+    let string = args[0].display(heap).to_string();
+    Ok(Value::Object(ObjectType::String(heap.intern_owned(string))))
+}
+
+pub fn num(heap: &mut Heap, args: &[Value]) -> Result<Value, String> {
+    match args[0] {
+        Value::Number(n) => Ok(Value::Number(n)),
+        Value::Object(ObjectType::String(s)) => {
+            let string = heap.get(s);
+            match string.trim() {
+                number if is_number_literal(number) => Ok(Value::Number(number.parse().unwrap())),
+                _ => Err(format!("Cannot convert '{}' to a number.", string)),
+            }
+        }
+        _ => Err("Argument must be a number or a string.".to_string()),
+    }
+}
+
+//Digits with an optional fraction, as a number is written in Lox. Rust would also accept signs,
+//exponents, inf and NaN
+fn is_number_literal(string: &str) -> bool {
+    let (whole, fraction) = match string.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (string, None),
+    };
+    let digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+
+    digits(whole) && fraction.is_none_or(digits)
+}
+
+pub fn len(heap: &mut Heap, args: &[Value]) -> Result<Value, String> {
+    match args[0] {
+        Value::Object(ObjectType::String(s)) => {
+            Ok(Value::Number(heap.get(s).chars().count() as f64))
+        }
+        Value::Object(ObjectType::List(l)) => Ok(Value::Number(heap.get(l).items.len() as f64)),
+        _ => Err("Argument must be a string or a list.".to_string()),
+    }
+}
+
+//Item of a list by its zero based index
+pub fn get(heap: &mut Heap, args: &[Value]) -> Result<Value, String> {
+    let items = match args[0] {
+        Value::Object(ObjectType::List(l)) => &heap.get(l).items,
+        _ => return Err("First argument must be a list.".to_string()),
+    };
+
+    match args[1] {
+        Value::Number(n) if n >= 0.0 && n.fract() == 0.0 && (n as usize) < items.len() => {
+            Ok(items[n as usize])
+        }
+        Value::Number(n) => Err(format!("Index {} out of range.", n)),
+        _ => Err("Index must be a number.".to_string()),
+    }
+}
+
+pub fn type_(heap: &mut Heap, args: &[Value]) -> Result<Value, String> {
+    let name = args[0].type_name();
+    Ok(Value::Object(ObjectType::String(heap.intern(name))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::List;
+
+    fn string(heap: &mut Heap, s: &str) -> Value {
+        Value::Object(ObjectType::String(heap.intern(s)))
+    }
+
+    #[test]
+    fn native_clock() {
+        let mut heap = Heap::new();
+
+        let result = clock(&mut heap, &[]);
+
+        assert!(matches!(result, Ok(Value::Number(n)) if n > 0.0));
+    }
+
+    #[test]
+    fn native_str() {
+        let mut heap = Heap::new();
+
+        let result = str(&mut heap, &[Value::Number(1.5)]);
+
+        assert_eq!(result, Ok(string(&mut heap, "1.5")));
+    }
+
+    #[test]
+    fn native_num() {
+        let mut heap = Heap::new();
+        let arg = string(&mut heap, " 12.5 ");
+
+        assert_eq!(num(&mut heap, &[arg]), Ok(Value::Number(12.5)));
+        assert_eq!(
+            num(&mut heap, &[Value::Number(1.0)]),
+            Ok(Value::Number(1.0))
+        );
+    }
+
+    #[test]
+    fn native_num_with_invalid_string() {
+        let mut heap = Heap::new();
+        let arg = string(&mut heap, "abc");
+
+        let result = num(&mut heap, &[arg]);
+
+        assert_eq!(result, Err("Cannot convert 'abc' to a number.".to_string()));
+    }
+
+    #[test]
+    fn native_num_rejects_non_lox_numbers() {
+        let mut heap = Heap::new();
+
+        for text in [
+            "inf", "infinity", "NaN", "+5", "-5", "1e3", ".5", "5.", "1.2.3", "",
+        ] {
+            let arg = string(&mut heap, text);
+            assert_eq!(
+                num(&mut heap, &[arg]),
+                Err(format!("Cannot convert '{}' to a number.", text)),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn native_num_with_invalid_type() {
+        let mut heap = Heap::new();
+
+        let result = num(&mut heap, &[Value::Nil]);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn native_len() {
+        let mut heap = Heap::new();
+        let arg = string(&mut heap, "héllo");
+
+        assert_eq!(len(&mut heap, &[arg]), Ok(Value::Number(5.0)));
+        assert!(len(&mut heap, &[Value::Number(1.0)]).is_err());
+    }
+
+    #[test]
+    fn native_len_list() {
+        let mut heap = Heap::new();
+        let list = heap.alloc(List::new(vec![Value::Nil, Value::Nil]));
+
+        let result = len(&mut heap, &[Value::Object(ObjectType::List(list))]);
+
+        assert_eq!(result, Ok(Value::Number(2.0)));
+    }
+
+    #[test]
+    fn native_get() {
+        let mut heap = Heap::new();
+        let list = heap.alloc(List::new(vec![Value::Nil, Value::Number(2.0)]));
+        let list = Value::Object(ObjectType::List(list));
+
+        assert_eq!(
+            get(&mut heap, &[list, Value::Number(1.0)]),
+            Ok(Value::Number(2.0))
+        );
+        assert_eq!(
+            get(&mut heap, &[list, Value::Number(2.0)]),
+            Err("Index 2 out of range.".to_string())
+        );
+        assert_eq!(
+            get(&mut heap, &[list, Value::Number(0.5)]),
+            Err("Index 0.5 out of range.".to_string())
+        );
+        assert_eq!(
+            get(&mut heap, &[Value::Nil, Value::Number(0.0)]),
+            Err("First argument must be a list.".to_string())
+        );
+    }
+
+    #[test]
+    fn native_type() {
+        let mut heap = Heap::new();
+
+        let result = type_(&mut heap, &[Value::Boolean(true)]);
+
+        assert_eq!(result, Ok(string(&mut heap, "boolean")));
+    }
+}
@@ -1,15 +1,172 @@
+use crate::chunk::Chunk;
+use crate::heap::{Gc, Heap};
+use crate::value::Value;
+use std::collections::HashMap;
 use std::fmt;
-use std::fmt::{Display, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 
-#[derive(Clone, Debug, PartialEq)]
+//Objects live in the VM's heap, values only hold handles to them
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum ObjectType {
-    String(Box<String>),
+    String(Gc<String>),
+    Function(Gc<Function>),
+    Closure(Gc<Closure>),
+    Class(Gc<Class>),
+    Instance(Gc<Instance>),
+    BoundMethod(Gc<BoundMethod>),
+    Native(Gc<Native>),
+    List(Gc<List>),
 }
 
-impl Display for ObjectType {
+#[derive(Debug)]
+pub struct Function {
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+    pub name: Option<String>, //None for the top level script
+}
+
+impl Function {
+    pub fn new(name: Option<String>, arity: usize, chunk: Chunk) -> Self {
+        Function {
+            arity,
+            upvalue_count: 0,
+            chunk,
+            name,
+        }
+    }
+}
+
+impl Display for Function {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        match self {
-            ObjectType::String(s) => write!(f, "{}", s),
+        match &self.name {
+            Some(name) => write!(f, "<fn {}>", name),
+            None => write!(f, "<script>"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Closure {
+    pub function: Gc<Function>,
+    pub upvalues: Vec<Gc<Upvalue>>,
+}
+
+impl Closure {
+    pub fn new(function: Gc<Function>, upvalues: Vec<Gc<Upvalue>>) -> Self {
+        Closure { function, upvalues }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Upvalue {
+    Open(usize), //Index of the captured variable on the stack
+    Closed(Value),
+}
+
+#[derive(Debug)]
+pub struct Class {
+    pub name: String,
+    pub methods: HashMap<Gc<String>, Gc<Closure>>,
+}
+
+impl Class {
+    pub fn new(name: String) -> Self {
+        Class {
+            name,
+            methods: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub class: Gc<Class>,
+    pub fields: HashMap<Gc<String>, Value>,
+}
+
+impl Instance {
+    pub fn new(class: Gc<Class>) -> Self {
+        Instance {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BoundMethod {
+    pub receiver: Value, //The instance `this` is bound to
+    pub method: Gc<Closure>,
+}
+
+//Only created by the host, scripts read it through the len and get natives
+#[derive(Debug)]
+pub struct List {
+    pub items: Vec<Value>,
+}
+
+impl List {
+    pub fn new(items: Vec<Value>) -> Self {
+        List { items }
+    }
+}
+
+//Arguments are only borrowed for the call, anything returned must be allocated in the heap
+pub type NativeFn = fn(&mut Heap, &[Value]) -> Result<Value, String>;
+
+pub struct Native {
+    pub name: String,
+    pub arity: usize,
+    pub function: NativeFn,
+}
+
+impl Native {
+    pub fn new(name: String, arity: usize, function: NativeFn) -> Self {
+        Native {
+            name,
+            arity,
+            function,
         }
     }
 }
+
+impl Debug for Native {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Native {{ name: {:?}, arity: {} }}",
+            self.name, self.arity
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heap::Heap;
+
+    #[test]
+    fn function_display() {
+        let function = Function::new(Some("foo".to_string()), 0, Chunk::new());
+
+        assert_eq!(function.to_string(), "<fn foo>");
+    }
+
+    #[test]
+    fn script_display() {
+        let function = Function::new(None, 0, Chunk::new());
+
+        assert_eq!(function.to_string(), "<script>");
+    }
+
+    #[test]
+    fn functions_only_equal_themselves() {
+        let mut heap = Heap::new();
+        let a = heap.alloc(Function::new(Some("a".to_string()), 0, Chunk::new()));
+        let b = heap.alloc(Function::new(Some("a".to_string()), 0, Chunk::new()));
+
+        assert_eq!(ObjectType::Function(a), ObjectType::Function(a));
+        assert_ne!(ObjectType::Function(a), ObjectType::Function(b));
+    }
+}
@@ -0,0 +1,56 @@
+use std::cell::RefCell;
+use std::io;
+use std::io::Write;
+use std::rc::Rc;
+
+//A sink that keeps everything written to it, for hosts that want a script's output as a string.
+//Clones share the same text, so one can be given to the VM and the other read afterwards
+#[derive(Clone, Default)]
+pub struct Buffer {
+    text: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Buffer::default()
+    }
+
+    //Everything written so far, leaving the buffer empty
+    pub fn take(&self) -> String {
+        let bytes = self.text.borrow_mut().split_off(0);
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.text.borrow()).into_owned()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.text.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffer_shared_between_clones() {
+        let buffer = Buffer::new();
+        let mut writer = buffer.clone();
+
+        write!(writer, "a").unwrap();
+        writeln!(writer, "{}", 1).unwrap();
+
+        assert_eq!(buffer.contents(), "a1\n");
+        assert_eq!(buffer.take(), "a1\n");
+        assert_eq!(buffer.contents(), "");
+    }
+}
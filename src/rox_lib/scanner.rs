@@ -1,5 +1,5 @@
 use crate::scanner::TokenType::*;
-use crate::RoxError;
+use crate::{RoxError, Span};
 
 macro_rules! two_char_token {
     ($scanner:ident, $token:expr, $first:path, $second:path) => {{
@@ -29,6 +29,11 @@ pub struct Scanner {
     source: Vec<char>,
     start: usize,
     current: usize,
+    start_offset: usize, //Byte offsets of start and current, the source is indexed by character
+    current_offset: usize,
+    start_column: usize,
+    start_line: i32,   //Line the token starts on, strings can span several
+    line_start: usize, //Index of the first character on the current line
     pub line: i32,
 }
 
@@ -38,6 +43,11 @@ impl Scanner {
             source: source.chars().collect(),
             start: 0,
             current: 0,
+            start_offset: 0,
+            current_offset: 0,
+            start_column: 1,
+            start_line: 1,
+            line_start: 0,
             line: 1,
         }
     }
@@ -46,6 +56,9 @@ impl Scanner {
         self.skip_whitespace();
 
         self.start = self.current;
+        self.start_offset = self.current_offset;
+        self.start_column = self.start - self.line_start + 1;
+        self.start_line = self.line;
 
         if self.is_at_end() {
             return Ok(Token::new(self, EOF));
@@ -81,20 +94,37 @@ impl Scanner {
             _ => (),
         }
 
-        Err(RoxError::new(
-            "Unexpected character.",
-            self.get_token(),
-            self.line,
-        ))
+        Err(self.error("Unexpected character."))
+    }
+
+    pub fn error(&self, message: &str) -> RoxError {
+        RoxError::new(message, self.get_token(), self.span())
     }
 
     pub fn get_token(&self) -> String {
         self.source[self.start..self.current].iter().collect()
     }
 
+    pub fn span(&self) -> Span {
+        Span::new(
+            self.start_offset,
+            self.current_offset - self.start_offset,
+            self.start_line,
+            self.start_column,
+        )
+    }
+
     fn advance(&mut self) -> char {
+        let c = self.source[self.current];
         self.current += 1;
-        self.source[self.current - 1]
+        self.current_offset += c.len_utf8();
+        c
+    }
+
+    fn new_line(&mut self) {
+        self.advance();
+        self.line += 1;
+        self.line_start = self.current;
     }
 
     pub fn match_token(&mut self, expected: char) -> bool {
@@ -106,24 +136,21 @@ impl Scanner {
             return false;
         }
 
-        self.current += 1;
+        self.advance();
         true
     }
 
     fn string(&mut self) -> Result<Token, RoxError> {
         while self.peek() != Some('"') && !self.is_at_end() {
             if self.peek() == Some('\n') {
-                self.line += 1;
+                self.new_line();
+            } else {
+                self.advance();
             }
-            self.advance();
         }
 
         if self.is_at_end() {
-            return Err(RoxError::new(
-                "Unterminated string.",
-                self.get_token(),
-                self.line,
-            ));
+            return Err(self.error("Unterminated string."));
         }
 
         //Consume double quote
@@ -159,7 +186,7 @@ impl Scanner {
     }
 
     fn identifier_type(&mut self) -> TokenType {
-        return match self.source[self.start] {
+        match self.source[self.start] {
             'a' => self.check_keyword(1, "nd", And),
             'c' => self.check_keyword(1, "lass", Class),
             'e' => self.check_keyword(1, "lse", Else),
@@ -173,7 +200,7 @@ impl Scanner {
                     };
                 }
 
-                return Identifier;
+                Identifier
             }
             'i' => self.check_keyword(1, "f", If),
             'n' => self.check_keyword(1, "il", Nil),
@@ -190,12 +217,12 @@ impl Scanner {
                     };
                 }
 
-                return Identifier;
+                Identifier
             }
             'v' => self.check_keyword(1, "ar", Var),
             'w' => self.check_keyword(1, "hile", While),
             _ => Identifier,
-        };
+        }
     }
 
     fn check_keyword(&self, start: usize, rest: &str, token_type: TokenType) -> TokenType {
@@ -203,7 +230,7 @@ impl Scanner {
             let slice: String = self.source[self.start + start..self.start + start + rest.len()]
                 .iter()
                 .collect();
-            if slice == rest.to_string() {
+            if slice == rest {
                 return token_type;
             }
         }
@@ -222,17 +249,10 @@ impl Scanner {
                     ' ' | '\r' | '\t' => {
                         self.advance();
                     }
-                    '\n' => {
-                        self.line += 1;
-                        self.advance();
-                    }
-                    '/' => {
-                        if self.peek_next() == Some('/') {
-                            while self.peek() != Some('\n') && !self.is_at_end() {
-                                self.advance();
-                            }
-                        } else {
-                            return;
+                    '\n' => self.new_line(),
+                    '/' if self.peek_next() == Some('/') => {
+                        while self.peek() != Some('\n') && !self.is_at_end() {
+                            self.advance();
                         }
                     }
                     _ => return,
@@ -246,11 +266,11 @@ impl Scanner {
     }
 
     fn peek(&self) -> Option<char> {
-        self.source.get(self.current).map(|&c| c)
+        self.source.get(self.current).copied()
     }
 
     fn peek_next(&self) -> Option<char> {
-        self.source.get(self.current + 1).map(|&c| c)
+        self.source.get(self.current + 1).copied()
     }
 }
 
@@ -258,7 +278,7 @@ impl Scanner {
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
-    pub line: i32,
+    pub span: Span,
 }
 
 impl Token {
@@ -266,7 +286,7 @@ impl Token {
         Token {
             token_type,
             lexeme: scanner.get_token(),
-            line: scanner.line,
+            span: scanner.span(),
         }
     }
 }
@@ -276,7 +296,7 @@ impl Default for Token {
         Token {
             token_type: EOF,
             lexeme: String::new(),
-            line: 0,
+            span: Span::default(),
         }
     }
 }
@@ -369,10 +389,10 @@ mod tests {
     #[test]
     fn scanner_is_at_end() {
         let mut scanner = Scanner::new("1");
-        assert_eq!(scanner.is_at_end(), false);
+        assert!(!scanner.is_at_end());
 
         scanner.advance();
-        assert_eq!(scanner.is_at_end(), true);
+        assert!(scanner.is_at_end());
     }
 
     #[test]
@@ -528,7 +548,7 @@ mod tests {
 
         let result = scanner.match_token('2');
 
-        assert_eq!(result, false);
+        assert!(!result);
     }
 
     #[test]
@@ -537,7 +557,7 @@ mod tests {
 
         let result = scanner.match_token('1');
 
-        assert_eq!(result, false);
+        assert!(!result);
     }
 
     #[test]
@@ -756,4 +776,40 @@ mod tests {
         let result = scanner.scan_token().unwrap();
         assert_eq!(result.token_type, GreaterEqual);
     }
+
+    #[test]
+    fn scanner_token_span() {
+        let mut scanner = Scanner::new("\"é\" = 1;\n  foo");
+
+        scanner.scan_token().unwrap();
+        scanner.scan_token().unwrap();
+        let number = scanner.scan_token().unwrap();
+        scanner.scan_token().unwrap();
+        let foo = scanner.scan_token().unwrap();
+
+        assert_eq!(number.lexeme, "1");
+        assert_eq!(number.span, Span::new(7, 1, 1, 7));
+        assert_eq!(foo.span, Span::new(12, 3, 2, 3));
+    }
+
+    #[test]
+    fn scanner_multiline_string_span() {
+        let mut scanner = Scanner::new("x\n\"a\nb\" y");
+
+        scanner.scan_token().unwrap();
+        let string = scanner.scan_token().unwrap();
+        let y = scanner.scan_token().unwrap();
+
+        assert_eq!(string.span, Span::new(2, 5, 2, 1));
+        assert_eq!(y.span, Span::new(8, 1, 3, 4));
+    }
+
+    #[test]
+    fn scanner_error_span() {
+        let mut scanner = Scanner::new("  |");
+
+        let error = scanner.scan_token().err().unwrap();
+
+        assert_eq!(error.span, Span::new(2, 1, 1, 3));
+    }
 }
@@ -1,8 +1,11 @@
+use crate::heap::Heap;
 use crate::object::ObjectType;
+use std::convert::{Infallible, TryFrom};
+use std::error::Error;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Value {
     Number(f64),
     Boolean(bool),
@@ -14,24 +17,229 @@ impl Value {
     pub fn is_falsey(&self) -> bool {
         matches!(self, Value::Nil | Value::Boolean(false))
     }
+
+    //The name scripts see from the `type` native
+    pub fn type_name(self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Boolean(_) => "boolean",
+            Value::Nil => "nil",
+            Value::Object(ObjectType::String(_)) => "string",
+            Value::Object(ObjectType::Class(_)) => "class",
+            Value::Object(ObjectType::Instance(_)) => "instance",
+            Value::Object(ObjectType::List(_)) => "list",
+            Value::Object(
+                ObjectType::Function(_)
+                | ObjectType::Closure(_)
+                | ObjectType::BoundMethod(_)
+                | ObjectType::Native(_),
+            ) => "function",
+        }
+    }
+
+    //Objects can only be printed by looking them up in the heap they were allocated in
+    pub fn display(self, heap: &Heap) -> ValueDisplay<'_> {
+        ValueDisplay { value: self, heap }
+    }
+}
+
+pub struct ValueDisplay<'a> {
+    value: Value,
+    heap: &'a Heap,
 }
 
-impl Display for Value {
+impl Display for ValueDisplay<'_> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        match self {
+        let heap = self.heap;
+        match self.value {
             Value::Number(n) => write!(f, "{}", n),
             Value::Boolean(b) => write!(f, "{}", b),
-            Value::Object(o) => write!(f, "{}", o),
             Value::Nil => write!(f, "nil"),
+            Value::Object(ObjectType::String(s)) => write!(f, "{}", heap.get(s)),
+            Value::Object(ObjectType::Function(function)) => write!(f, "{}", heap.get(function)),
+            Value::Object(ObjectType::Closure(closure)) => {
+                write!(f, "{}", heap.get(heap.get(closure).function))
+            }
+            Value::Object(ObjectType::Class(class)) => write!(f, "{}", heap.get(class).name),
+            Value::Object(ObjectType::Instance(instance)) => {
+                write!(f, "{} instance", heap.get(heap.get(instance).class).name)
+            }
+            Value::Object(ObjectType::BoundMethod(bound)) => {
+                let method = heap.get(bound).method;
+                write!(f, "{}", heap.get(heap.get(method).function))
+            }
+            Value::Object(ObjectType::Native(_)) => write!(f, "<native fn>"),
+            Value::Object(ObjectType::List(list)) => {
+                write!(f, "[")?;
+                for (i, item) in heap.get(list).items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item.display(heap))?;
+                }
+                write!(f, "]")
+            }
         }
     }
 }
 
-impl Into<f64> for Value {
-    fn into(self) -> f64 {
-        match self {
-            Value::Number(n) => n,
-            _ => panic!("Value {} is not a number", self),
+//A value that did not have the type a Rust caller asked for, or a global that does not exist
+#[derive(Debug, PartialEq)]
+pub struct ValueError {
+    pub message: String,
+}
+
+impl ValueError {
+    pub fn new(message: &str) -> Self {
+        ValueError {
+            message: message.to_string(),
+        }
+    }
+
+    fn expected(expected: &str, value: Value) -> Self {
+        ValueError::new(&format!(
+            "Expected a {} but got {}.",
+            expected,
+            value.type_name()
+        ))
+    }
+}
+
+impl Display for ValueError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for ValueError {}
+
+impl From<Infallible> for ValueError {
+    fn from(never: Infallible) -> Self {
+        match never {}
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Boolean(b)
+    }
+}
+
+//None becomes nil
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(option: Option<T>) -> Self {
+        option.map_or(Value::Nil, Into::into)
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = ValueError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(n) => Ok(n),
+            _ => Err(ValueError::expected("number", value)),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = ValueError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            _ => Err(ValueError::expected("boolean", value)),
+        }
+    }
+}
+
+//Nil becomes None. A blanket impl over Option<T> would overlap the standard library's
+//TryFrom<T> for Option<T>, so each type gets its own
+macro_rules! option_try_from {
+    ($($type:ty),*) => {
+        $(
+            impl TryFrom<Value> for Option<$type> {
+                type Error = ValueError;
+
+                fn try_from(value: Value) -> Result<Self, Self::Error> {
+                    match value {
+                        Value::Nil => Ok(None),
+                        _ => <$type>::try_from(value).map(Some),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+option_try_from!(f64, bool);
+
+//Converts a Rust value into a Value in a heap. Strings have to be interned, so they can only be
+//converted this way, anything with a From impl gets this for free
+pub trait IntoValue {
+    fn into_value(self, heap: &mut Heap) -> Value;
+}
+
+impl<T: Into<Value>> IntoValue for T {
+    fn into_value(self, _heap: &mut Heap) -> Value {
+        self.into()
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self, heap: &mut Heap) -> Value {
+        Value::Object(ObjectType::String(heap.intern(self)))
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self, heap: &mut Heap) -> Value {
+        Value::Object(ObjectType::String(heap.intern_owned(self)))
+    }
+}
+
+impl IntoValue for Option<String> {
+    fn into_value(self, heap: &mut Heap) -> Value {
+        self.map_or(Value::Nil, |s| s.into_value(heap))
+    }
+}
+
+//The reverse of IntoValue, anything with a TryFrom impl gets this for free
+pub trait FromValue: Sized {
+    fn from_value(value: Value, heap: &Heap) -> Result<Self, ValueError>;
+}
+
+impl<T> FromValue for T
+where
+    T: TryFrom<Value>,
+    ValueError: From<T::Error>,
+{
+    fn from_value(value: Value, _heap: &Heap) -> Result<Self, ValueError> {
+        Ok(T::try_from(value)?)
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value, heap: &Heap) -> Result<Self, ValueError> {
+        match value {
+            Value::Object(ObjectType::String(s)) => Ok(heap.get(s).clone()),
+            _ => Err(ValueError::expected("string", value)),
+        }
+    }
+}
+
+impl FromValue for Option<String> {
+    fn from_value(value: Value, heap: &Heap) -> Result<Self, ValueError> {
+        match value {
+            Value::Nil => Ok(None),
+            _ => String::from_value(value, heap).map(Some),
         }
     }
 }
@@ -39,6 +247,8 @@ impl Into<f64> for Value {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chunk::Chunk;
+    use crate::object::{Class, Closure, Function, Instance, List};
 
     #[test]
     fn value_is_falsey() {
@@ -48,26 +258,118 @@ mod tests {
 
     #[test]
     fn value_is_truthy() {
-        assert_eq!(Value::Boolean(true).is_falsey(), false);
-        assert_eq!(Value::Number(0.0).is_falsey(), false);
+        assert!(!Value::Boolean(true).is_falsey());
+        assert!(!Value::Number(0.0).is_falsey());
     }
 
     #[test]
-    fn value_into_valid_number() {
-        let result: f64 = Value::Number(1.0).into();
+    fn value_try_into_valid_number() {
+        let result = f64::try_from(Value::Number(1.0));
 
-        assert_eq!(result, 1.0);
+        assert_eq!(result, Ok(1.0));
     }
 
     #[test]
-    #[should_panic]
-    fn value_into_nil_panics() {
-        let _: f64 = Value::Nil.into();
+    fn value_try_into_number_from_nil() {
+        let result = f64::try_from(Value::Nil);
+
+        assert_eq!(
+            result,
+            Err(ValueError::new("Expected a number but got nil."))
+        );
     }
 
     #[test]
-    #[should_panic]
-    fn value_into_bool_panics() {
-        let _: f64 = Value::Boolean(false).into();
+    fn value_try_into_bool() {
+        assert_eq!(bool::try_from(Value::Boolean(true)), Ok(true));
+        assert!(bool::try_from(Value::Number(0.0)).is_err());
+    }
+
+    #[test]
+    fn value_try_into_option() {
+        assert_eq!(Option::<f64>::try_from(Value::Nil), Ok(None));
+        assert_eq!(
+            Option::<bool>::try_from(Value::Boolean(false)),
+            Ok(Some(false))
+        );
+        assert!(Option::<f64>::try_from(Value::Boolean(false)).is_err());
+    }
+
+    #[test]
+    fn value_from_rust_types() {
+        assert_eq!(Value::from(2.5), Value::Number(2.5));
+        assert_eq!(Value::from(true), Value::Boolean(true));
+        assert_eq!(Value::from(Some(1.0)), Value::Number(1.0));
+        assert_eq!(Value::from(None::<bool>), Value::Nil);
+    }
+
+    #[test]
+    fn value_string_conversions() {
+        let mut heap = Heap::new();
+
+        let value = "foo".into_value(&mut heap);
+
+        assert_eq!(value, Value::Object(ObjectType::String(heap.intern("foo"))));
+        assert_eq!(String::from_value(value, &heap), Ok("foo".to_string()));
+        assert_eq!(Option::<String>::from_value(Value::Nil, &heap), Ok(None));
+        assert_eq!(
+            String::from_value(Value::Number(1.0), &heap),
+            Err(ValueError::new("Expected a string but got number."))
+        );
+    }
+
+    #[test]
+    fn value_display() {
+        let heap = Heap::new();
+
+        assert_eq!(Value::Number(1.5).display(&heap).to_string(), "1.5");
+        assert_eq!(Value::Boolean(true).display(&heap).to_string(), "true");
+        assert_eq!(Value::Nil.display(&heap).to_string(), "nil");
+    }
+
+    #[test]
+    fn string_display() {
+        let mut heap = Heap::new();
+        let string = Value::Object(ObjectType::String(heap.intern("foo")));
+
+        assert_eq!(string.display(&heap).to_string(), "foo");
+    }
+
+    #[test]
+    fn closure_display() {
+        let mut heap = Heap::new();
+        let function = heap.alloc(Function::new(Some("foo".to_string()), 0, Chunk::new()));
+        let closure = heap.alloc(Closure::new(function, Vec::new()));
+
+        let value = Value::Object(ObjectType::Closure(closure));
+
+        assert_eq!(value.display(&heap).to_string(), "<fn foo>");
+    }
+
+    #[test]
+    fn class_and_instance_display() {
+        let mut heap = Heap::new();
+        let class = heap.alloc(Class::new("Foo".to_string()));
+        let instance = heap.alloc(Instance::new(class));
+
+        let class = Value::Object(ObjectType::Class(class));
+        let instance = Value::Object(ObjectType::Instance(instance));
+
+        assert_eq!(class.display(&heap).to_string(), "Foo");
+        assert_eq!(instance.display(&heap).to_string(), "Foo instance");
+    }
+
+    #[test]
+    fn list_display() {
+        let mut heap = Heap::new();
+        let item = Value::Object(ObjectType::String(heap.intern("a")));
+        let list = heap.alloc(List::new(vec![item, Value::Number(2.0)]));
+        let empty = heap.alloc(List::new(Vec::new()));
+
+        let list = Value::Object(ObjectType::List(list));
+        let empty = Value::Object(ObjectType::List(empty));
+
+        assert_eq!(list.display(&heap).to_string(), "[a, 2]");
+        assert_eq!(empty.display(&heap).to_string(), "[]");
     }
 }
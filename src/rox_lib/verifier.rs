@@ -0,0 +1,610 @@
+use crate::bytecode::BytecodeError;
+use crate::chunk::OpCode::*;
+use crate::chunk::{Chunk, DecodeError};
+use crate::debug::{instructions, Instruction, Operands};
+use crate::heap::Heap;
+use crate::object::{Function, ObjectType};
+use crate::value::Value;
+
+//Checks a top level script chunk and every function nested in it, so that running it can only
+//fail with a runtime error. Each function must:
+// - only contain known opcodes whose operands fit in the code
+// - only use constants, locals and upvalues that exist, with names being strings
+// - only jump to the start of an instruction
+// - reach every instruction with the same stack depth on every path, never popping below the
+//   frame and never running past the end of the code
+// - close every captured local before popping it, so no open upvalue outlives its slot
+pub fn verify(chunk: &Chunk, heap: &Heap) -> Result<(), BytecodeError> {
+    Verifier {
+        chunk,
+        heap,
+        name: "<script>".to_string(),
+        arity: 0,
+        upvalue_count: 0,
+    }
+    .verify()
+}
+
+//What is known about the stack before an instruction, the slots are relative to the frame
+#[derive(Clone, PartialEq)]
+struct StackState {
+    depth: usize,
+    captured: Vec<usize>, //Slots with an open upvalue, in order
+}
+
+impl StackState {
+    fn merge(mut self, other: StackState) -> StackState {
+        self.captured.extend(other.captured);
+        self.captured.sort_unstable();
+        self.captured.dedup();
+        self
+    }
+}
+
+struct Verifier<'a> {
+    chunk: &'a Chunk,
+    heap: &'a Heap,
+    name: String,
+    arity: usize,
+    upvalue_count: usize,
+}
+
+impl<'a> Verifier<'a> {
+    fn verify(&self) -> Result<(), BytecodeError> {
+        for constant in &self.chunk.constants {
+            if let Value::Object(ObjectType::Function(f)) = constant {
+                let function: &Function = self.heap.get(*f);
+                Verifier {
+                    chunk: &function.chunk,
+                    heap: self.heap,
+                    name: function.to_string(),
+                    arity: function.arity,
+                    upvalue_count: function.upvalue_count,
+                }
+                .verify()?;
+            }
+        }
+
+        let instructions = self.decode()?;
+        self.check_stack(&instructions)
+    }
+
+    fn error(&self, message: &str, offset: usize) -> BytecodeError {
+        BytecodeError::new(&format!(
+            "{} at offset {} in {}.",
+            message, offset, self.name
+        ))
+    }
+
+    //Decodes the code, checking every operand that doesn't depend on the stack
+    fn decode(&self) -> Result<Vec<Instruction>, BytecodeError> {
+        let mut decoded = Vec::new();
+        let mut offset = 0;
+
+        //Decoding stops at the first error, so it is always at the end of what was decoded
+        for instruction in instructions(self.chunk, self.heap) {
+            let instruction = match instruction {
+                Ok(instruction) => instruction,
+                Err(DecodeError::UnknownOpcode(byte)) => {
+                    return Err(self.error(&format!("Unknown opcode {}", byte), offset))
+                }
+                Err(DecodeError::Truncated(_)) => {
+                    return Err(self.error("Truncated instruction", offset))
+                }
+                Err(DecodeError::InvalidCapture(_)) => {
+                    return Err(self.error("Invalid upvalue capture flag", offset))
+                }
+            };
+
+            self.check_operands(&instruction)?;
+            offset += instruction.length();
+            decoded.push(instruction);
+        }
+
+        Ok(decoded)
+    }
+
+    fn check_operands(&self, instruction: &Instruction) -> Result<(), BytecodeError> {
+        let offset = instruction.offset;
+
+        match (instruction.opcode, &instruction.operands) {
+            (Constant | ConstantLong, Operands::Constant(constant)) => {
+                self.constant(*constant, offset)?;
+            }
+            (_, Operands::Constant(constant)) => match self.constant(*constant, offset)? {
+                Value::Object(ObjectType::String(_)) => (),
+                _ => return Err(self.error("Name operand is not a string", offset)),
+            },
+            (GetUpvalue | SetUpvalue, Operands::Byte(slot))
+                if *slot as usize >= self.upvalue_count =>
+            {
+                return Err(self.error(&format!("Upvalue {} out of range", slot), offset));
+            }
+            (_, Operands::Closure { constant, upvalues }) => {
+                match self.constant(*constant, offset)? {
+                    Value::Object(ObjectType::Function(_)) => (),
+                    _ => return Err(self.error("Closure operand is not a function", offset)),
+                }
+
+                for capture in upvalues {
+                    if !capture.is_local && capture.index as usize >= self.upvalue_count {
+                        return Err(self.error(
+                            &format!("Captured upvalue {} out of range", capture.index),
+                            offset,
+                        ));
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn constant(&self, index: usize, offset: usize) -> Result<Value, BytecodeError> {
+        match self.chunk.constants.get(index) {
+            Some(value) => Ok(*value),
+            None => Err(self.error(&format!("Constant {} out of range", index), offset)),
+        }
+    }
+
+    //Follows every path through the code from the entry, where the callee and its arguments
+    //are already on the stack
+    fn check_stack(&self, instructions: &[Instruction]) -> Result<(), BytecodeError> {
+        let mut index_of = vec![None; self.chunk.code.len()];
+        for (index, instruction) in instructions.iter().enumerate() {
+            index_of[instruction.offset] = Some(index);
+        }
+
+        let mut states: Vec<Option<StackState>> = vec![None; instructions.len()];
+        //Each entry is where execution continues, the stack there and the instruction it
+        //continues from, which is the one reported if the target isn't valid
+        let entry = StackState {
+            depth: self.arity + 1,
+            captured: Vec::new(),
+        };
+        let mut pending = vec![(0, entry, 0)];
+
+        while let Some((offset, state, from)) = pending.pop() {
+            let index = match index_of.get(offset) {
+                Some(Some(index)) => *index,
+                Some(None) => {
+                    return Err(self.error("Jump into the middle of an instruction", from))
+                }
+                None => return Err(self.error("Execution runs past the end of the code", from)),
+            };
+
+            //A slot captured on only some of the paths here may still have an open upvalue, so
+            //the paths are merged and followed again until nothing new is captured
+            let state = match states[index].take() {
+                Some(previous) if previous.depth != state.depth => {
+                    return Err(self.error(
+                        &format!(
+                            "Inconsistent stack depth {} and {}",
+                            previous.depth, state.depth
+                        ),
+                        offset,
+                    ))
+                }
+                Some(previous) if state.captured.iter().all(|s| previous.captured.contains(s)) => {
+                    states[index] = Some(previous);
+                    continue;
+                }
+                Some(previous) => previous.merge(state),
+                None => state,
+            };
+            states[index] = Some(state.clone());
+
+            let instruction = &instructions[index];
+            let StackState {
+                depth,
+                mut captured,
+            } = state;
+            let (needed, pushed) = self.stack_effect(instruction, depth)?;
+            if depth < needed {
+                return Err(self.error("Stack underflow", offset));
+            }
+            let depth = depth - needed + pushed;
+            let next = offset + instruction.length();
+
+            //An open upvalue points into the stack, so a captured slot has to be closed before
+            //it is popped. Returning closes every upvalue of the frame
+            match (instruction.opcode, &instruction.operands) {
+                (_, Operands::Closure { upvalues, .. }) => {
+                    for capture in upvalues.iter().filter(|c| c.is_local) {
+                        let slot = capture.index as usize;
+                        if let Err(position) = captured.binary_search(&slot) {
+                            captured.insert(position, slot);
+                        }
+                    }
+                }
+                (CloseUpvalue, _) => captured.retain(|slot| *slot != depth),
+                (Return, _) => captured.clear(),
+                _ => (),
+            }
+            if let Some(slot) = captured.iter().find(|slot| **slot >= depth) {
+                return Err(self.error(
+                    &format!("Captured local {} popped without being closed", slot),
+                    offset,
+                ));
+            }
+            let state = StackState { depth, captured };
+
+            match (instruction.opcode, &instruction.operands) {
+                (Return, _) => (),
+                (Loop, Operands::Jump { target, .. }) if *target < 0 => {
+                    return Err(self.error("Loop before the start of the code", offset))
+                }
+                (Jump | Loop, Operands::Jump { target, .. }) => {
+                    pending.push((*target as usize, state, offset))
+                }
+                (JumpIfFalse, Operands::Jump { target, .. }) => {
+                    pending.push((next, state.clone(), offset));
+                    pending.push((*target as usize, state, offset));
+                }
+                _ => pending.push((next, state, offset)),
+            }
+        }
+
+        Ok(())
+    }
+
+    //Number of values the instruction needs on the stack and how many it leaves in their place
+    fn stack_effect(
+        &self,
+        instruction: &Instruction,
+        depth: usize,
+    ) -> Result<(usize, usize), BytecodeError> {
+        let operand = match instruction.operands {
+            Operands::Byte(byte) => byte as usize,
+            _ => 0,
+        };
+
+        let effect = match instruction.opcode {
+            Constant | ConstantLong | Nil | True | False | GetGlobal | GetGlobalLong
+            | GetUpvalue | Class | ClassLong => (0, 1),
+            Pop | DefineGlobal | DefineGlobalLong | Print | CloseUpvalue | Return => (1, 0),
+            SetGlobal | SetGlobalLong | SetUpvalue | GetProperty | GetPropertyLong | Not
+            | Negate | JumpIfFalse => (1, 1),
+            Equal | Greater | Less | Add | Subtract | Multiple | Divide | SetProperty
+            | SetPropertyLong | GetSuper | GetSuperLong => (2, 1),
+            Inherit | Method | MethodLong => (2, 1),
+            Jump | Loop => (0, 0),
+            Call => (operand + 1, 1),
+            GetLocal | SetLocal if operand >= depth => {
+                return Err(self.error(
+                    &format!("Local slot {} out of range", operand),
+                    instruction.offset,
+                ))
+            }
+            GetLocal => (0, 1),
+            SetLocal => (1, 1),
+            Closure | ClosureLong => {
+                let upvalues = match &instruction.operands {
+                    Operands::Closure { upvalues, .. } => upvalues.as_slice(),
+                    _ => &[],
+                };
+                //A local function captures itself from the slot it is about to be pushed to
+                for capture in upvalues {
+                    if capture.is_local && capture.index as usize > depth {
+                        return Err(self.error(
+                            &format!("Captured local {} out of range", capture.index),
+                            instruction.offset,
+                        ));
+                    }
+                }
+                (0, 1)
+            }
+        };
+
+        Ok(effect)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+    use std::fs;
+    use std::path::Path;
+
+    fn chunk(code: Vec<u8>, constants: Vec<Value>) -> Chunk {
+        let mut chunk = Chunk::new();
+        for byte in code {
+            chunk.write(byte, 0);
+        }
+        chunk.constants = constants;
+        chunk
+    }
+
+    fn verify_error(code: Vec<u8>, constants: Vec<Value>) -> String {
+        verify(&chunk(code, constants), &Heap::new())
+            .unwrap_err()
+            .message
+    }
+
+    fn lox_files(directory: &Path, files: &mut Vec<String>) {
+        for entry in fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                lox_files(&path, files);
+            } else if path.extension().is_some_and(|e| e == "lox") {
+                files.push(fs::read_to_string(path).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn verifier_accepts_compiled_test_scripts() {
+        let mut sources = Vec::new();
+        lox_files(Path::new("tests/resources"), &mut sources);
+
+        for source in sources {
+            let mut heap = Heap::new();
+            if let Ok(chunk) = compile(&source, &mut heap) {
+                assert_eq!(verify(&chunk, &heap), Ok(()), "{}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn verifier_accepts_long_operands() {
+        let mut heap = Heap::new();
+        let literals: String = (0..300).map(|i| format!("var a{} = {};", i, i)).collect();
+        let source =
+            literals + "fun f(x) { fun g() { return x; } } class A < B { m() { super.m(); } }";
+
+        let chunk = compile(&source, &mut heap).unwrap();
+
+        assert_eq!(verify(&chunk, &heap), Ok(()));
+    }
+
+    #[test]
+    fn verifier_accepts_minimal_script() {
+        let chunk = chunk(vec![Nil as u8, Return as u8], Vec::new());
+
+        assert_eq!(verify(&chunk, &Heap::new()), Ok(()));
+    }
+
+    #[test]
+    fn verifier_unknown_opcode() {
+        let result = verify_error(vec![200], Vec::new());
+
+        assert_eq!(
+            result,
+            "Unknown opcode 200 at offset 0 in <script>.".to_string()
+        );
+    }
+
+    #[test]
+    fn verifier_truncated_operand() {
+        let result = verify_error(vec![Nil as u8, Jump as u8, 0], Vec::new());
+
+        assert_eq!(
+            result,
+            "Truncated instruction at offset 1 in <script>.".to_string()
+        );
+    }
+
+    #[test]
+    fn verifier_constant_out_of_range() {
+        let result = verify_error(vec![Constant as u8, 1, Return as u8], vec![Value::Nil]);
+
+        assert_eq!(
+            result,
+            "Constant 1 out of range at offset 0 in <script>.".to_string()
+        );
+    }
+
+    #[test]
+    fn verifier_global_name_must_be_string() {
+        let result = verify_error(
+            vec![GetGlobal as u8, 0, Return as u8],
+            vec![Value::Number(1.0)],
+        );
+
+        assert_eq!(
+            result,
+            "Name operand is not a string at offset 0 in <script>.".to_string()
+        );
+    }
+
+    #[test]
+    fn verifier_upvalue_out_of_range() {
+        let result = verify_error(vec![GetUpvalue as u8, 0, Return as u8], Vec::new());
+
+        assert_eq!(
+            result,
+            "Upvalue 0 out of range at offset 0 in <script>.".to_string()
+        );
+    }
+
+    #[test]
+    fn verifier_jump_into_instruction() {
+        let result = verify_error(
+            vec![
+                Nil as u8,
+                Jump as u8,
+                0,
+                2,
+                Nil as u8,
+                Constant as u8,
+                0,
+                Return as u8,
+            ],
+            vec![Value::Nil],
+        );
+
+        assert_eq!(
+            result,
+            "Jump into the middle of an instruction at offset 1 in <script>.".to_string()
+        );
+    }
+
+    #[test]
+    fn verifier_jump_past_end() {
+        let result = verify_error(vec![Jump as u8, 0, 9, Nil as u8, Return as u8], Vec::new());
+
+        assert_eq!(
+            result,
+            "Execution runs past the end of the code at offset 0 in <script>.".to_string()
+        );
+    }
+
+    #[test]
+    fn verifier_falls_off_end() {
+        let result = verify_error(vec![Nil as u8], Vec::new());
+
+        assert_eq!(
+            result,
+            "Execution runs past the end of the code at offset 0 in <script>.".to_string()
+        );
+    }
+
+    #[test]
+    fn verifier_stack_underflow() {
+        let result = verify_error(
+            vec![Pop as u8, Pop as u8, Nil as u8, Return as u8],
+            Vec::new(),
+        );
+
+        assert_eq!(
+            result,
+            "Stack underflow at offset 1 in <script>.".to_string()
+        );
+    }
+
+    #[test]
+    fn verifier_local_out_of_range() {
+        let result = verify_error(vec![GetLocal as u8, 1, Return as u8], Vec::new());
+
+        assert_eq!(
+            result,
+            "Local slot 1 out of range at offset 0 in <script>.".to_string()
+        );
+    }
+
+    #[test]
+    fn verifier_inconsistent_depth() {
+        //Only the fall through path pushes before the branches meet
+        let result = verify_error(
+            vec![True as u8, JumpIfFalse as u8, 0, 1, Nil as u8, Return as u8],
+            Vec::new(),
+        );
+
+        assert_eq!(
+            result,
+            "Inconsistent stack depth 2 and 3 at offset 5 in <script>.".to_string()
+        );
+    }
+
+    #[test]
+    fn verifier_checks_nested_functions() {
+        let mut heap = Heap::new();
+        let body = chunk(vec![Pop as u8, Pop as u8, Return as u8], Vec::new());
+        let function = heap.alloc(Function::new(Some("f".to_string()), 0, body));
+        let script = chunk(
+            vec![Nil as u8, Return as u8],
+            vec![Value::Object(ObjectType::Function(function))],
+        );
+
+        let result = verify(&script, &heap).unwrap_err().message;
+
+        assert_eq!(result, "Stack underflow at offset 1 in <fn f>.".to_string());
+    }
+
+    #[test]
+    fn verifier_captured_local_out_of_range() {
+        let mut heap = Heap::new();
+        let body = chunk(vec![Nil as u8, Return as u8], Vec::new());
+        let mut function = Function::new(Some("f".to_string()), 0, body);
+        function.upvalue_count = 1;
+        let function = heap.alloc(function);
+        let script = chunk(
+            vec![Closure as u8, 0, 1, 5, Return as u8],
+            vec![Value::Object(ObjectType::Function(function))],
+        );
+
+        let result = verify(&script, &heap).unwrap_err().message;
+
+        assert_eq!(
+            result,
+            "Captured local 5 out of range at offset 0 in <script>.".to_string()
+        );
+    }
+
+    #[test]
+    fn verifier_invalid_capture_flag() {
+        let mut heap = Heap::new();
+        let body = chunk(vec![Nil as u8, Return as u8], Vec::new());
+        let mut function = Function::new(Some("f".to_string()), 0, body);
+        function.upvalue_count = 1;
+        let function = heap.alloc(function);
+        let script = chunk(
+            vec![Nil as u8, Closure as u8, 0, 2, 0, Return as u8],
+            vec![Value::Object(ObjectType::Function(function))],
+        );
+
+        let result = verify(&script, &heap).unwrap_err().message;
+
+        assert_eq!(
+            result,
+            "Invalid upvalue capture flag at offset 1 in <script>.".to_string()
+        );
+    }
+
+    #[test]
+    fn verifier_captured_local_popped() {
+        let mut heap = Heap::new();
+        let body = chunk(vec![Nil as u8, Return as u8], Vec::new());
+        let mut function = Function::new(Some("f".to_string()), 0, body);
+        function.upvalue_count = 1;
+        let function = heap.alloc(function);
+        let script = chunk(
+            vec![
+                Nil as u8,
+                Closure as u8,
+                0,
+                1,
+                1,
+                Pop as u8,
+                Pop as u8,
+                Nil as u8,
+                Return as u8,
+            ],
+            vec![Value::Object(ObjectType::Function(function))],
+        );
+
+        let result = verify(&script, &heap).unwrap_err().message;
+
+        assert_eq!(
+            result,
+            "Captured local 1 popped without being closed at offset 6 in <script>.".to_string()
+        );
+    }
+
+    #[test]
+    fn verifier_accepts_closed_capture() {
+        let mut heap = Heap::new();
+        let body = chunk(vec![Nil as u8, Return as u8], Vec::new());
+        let mut function = Function::new(Some("f".to_string()), 0, body);
+        function.upvalue_count = 1;
+        let function = heap.alloc(function);
+        let script = chunk(
+            vec![
+                Nil as u8,
+                Closure as u8,
+                0,
+                1,
+                1,
+                Pop as u8,
+                CloseUpvalue as u8,
+                Nil as u8,
+                Return as u8,
+            ],
+            vec![Value::Object(ObjectType::Function(function))],
+        );
+
+        assert_eq!(verify(&script, &heap), Ok(()));
+    }
+}
@@ -1,74 +1,390 @@
+use crate::bytecode::BytecodeError;
 use crate::chunk::OpCode::*;
 use crate::chunk::{Chunk, OpCode};
-use crate::compiler::compile;
-use crate::debug::disassemble_instruction;
-use crate::object::ObjectType;
-use crate::value::Value;
+use crate::compiler::{compile, compile_expression, compile_repl};
+use crate::debug::{disassemble_instruction, disassemble_program};
+use crate::heap::{Gc, Heap};
+use crate::native;
+use crate::object::{
+    BoundMethod, Class, Closure, Function, Instance, List, Native, NativeFn, ObjectType, Upvalue,
+};
+use crate::value::{FromValue, IntoValue, Value, ValueError};
+use crate::verifier::verify;
+use crate::RoxError;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::time::{Duration, Instant};
+
+const FRAMES_MAX: usize = 64;
+const STACK_MAX: usize = FRAMES_MAX * 256;
+const TIME_CHECK_INTERVAL: u64 = 1024; //Instructions run between looking at the clock
 
 macro_rules! binary_op {
     ($vm:ident, $type:expr, $op:tt) => (
         {
-            if matches!($vm.peek(0), Value::Number(_)) && matches!($vm.peek(1), Value::Number(_)) {
-                let b: f64 = $vm.pop().into();
-                let a: f64 = $vm.pop().into();
+            if let (Value::Number(a), Value::Number(b)) = ($vm.peek(1), $vm.peek(0)) {
+                $vm.pop();
+                $vm.pop();
                 $vm.push($type(a $op b));
             } else {
-                $vm.runtime_error("Operands must be numbers.");
-                return InterpretResult::RuntimeError;
+                return Err($vm.runtime_error("Operands must be numbers."));
             }
-        };
+        }
     )
 }
 
+struct CallFrame {
+    closure: Gc<Closure>,
+    function: Gc<Function>, //Cached from the closure to avoid a second lookup for every byte read
+    ip: usize,              //Instruction Pointer
+    slot_offset: usize,     //Index of the first stack slot the function can use
+}
+
 pub struct VM {
-    chunk: Chunk,
-    ip: usize, //Instruction Pointer
+    frames: Vec<CallFrame>,
     stack: Vec<Value>,
-    globals: HashMap<String, Value>,
+    globals: HashMap<Gc<String>, Value>,
+    open_upvalues: Vec<Gc<Upvalue>>,
+    roots: Vec<Value>, //Values held by Rust that must survive collections, see root
+    scripts: Vec<Gc<Function>>, //Loaded but not yet run, see LoadedScript
+    heap: Heap,
+    init_string: Gc<String>,
+    trace_execution: bool,
+    print_code: bool,
+    output: Box<dyn Write>,       //Where `print`, traces and code dumps go
+    error_output: Box<dyn Write>, //Where report writes errors
+    limits: Limits,
+    instructions_run: u64, //Since the current script or call from Rust started
+    deadline: Option<Instant>,
+}
+
+//Bounds on a single script or call from Rust, so that untrusted code is guaranteed to stop
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub instructions: Option<u64>,
+    pub time: Option<Duration>,
+    pub stack: usize,      //Values on the stack, across every frame
+    pub call_depth: usize, //Frames, including the top level script
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            instructions: None,
+            time: None,
+            stack: STACK_MAX,
+            call_depth: FRAMES_MAX,
+        }
+    }
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VM {
     pub fn new() -> Self {
-        VM {
-            chunk: Chunk::new(), //Create throwaway Chunk to avoid Option<Chunk>
-            ip: 0,
+        let mut heap = Heap::new();
+        let init_string = heap.intern("init");
+
+        let mut vm = VM {
+            frames: Vec::with_capacity(FRAMES_MAX),
             stack: Vec::new(),
             globals: HashMap::new(),
+            open_upvalues: Vec::new(),
+            roots: Vec::new(),
+            scripts: Vec::new(),
+            heap,
+            init_string,
+            trace_execution: false,
+            print_code: false,
+            output: Box::new(io::stdout()),
+            error_output: Box::new(io::stderr()),
+            limits: Limits::default(),
+            instructions_run: 0,
+            deadline: None,
+        };
+
+        vm.define_native("clock", 0, native::clock);
+        vm.define_native("str", 1, native::str);
+        vm.define_native("num", 1, native::num);
+        vm.define_native("len", 1, native::len);
+        vm.define_native("type", 1, native::type_);
+        vm.define_native("get", 2, native::get);
+
+        vm
+    }
+
+    //Exposes a Rust function to scripts as a global, an Err returned from it becomes a runtime error
+    pub fn define_native(&mut self, name: &str, arity: usize, function: NativeFn) {
+        let native = self
+            .heap
+            .alloc(Native::new(name.to_string(), arity, function));
+        let name = self.heap.intern(name);
+        self.globals
+            .insert(name, Value::Object(ObjectType::Native(native)));
+    }
+
+    //Exposes the command line arguments to scripts as the global list `args`
+    pub fn set_args(&mut self, args: &[String]) {
+        let items = args
+            .iter()
+            .map(|arg| Value::Object(ObjectType::String(self.heap.intern(arg))))
+            .collect();
+        let list = self.heap.alloc(List::new(items));
+        let name = self.heap.intern("args");
+        self.globals
+            .insert(name, Value::Object(ObjectType::List(list)));
+    }
+
+    //Collect garbage after every allocation, only useful for finding GC bugs
+    pub fn set_stress_gc(&mut self, stress_gc: bool) {
+        self.heap.stress_gc = stress_gc;
+    }
+
+    //Print the stack and each instruction to stdout before it is executed
+    pub fn set_trace_execution(&mut self, trace_execution: bool) {
+        self.trace_execution = trace_execution;
+    }
+
+    //Print the disassembled code of every chunk to stdout before running it
+    pub fn set_print_code(&mut self, print_code: bool) {
+        self.print_code = print_code;
+    }
+
+    //Each limit that is exceeded stops the script with its own runtime error
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    //Sends everything scripts print, and any trace or code dump, to out instead of stdout
+    pub fn set_output<W: Write + 'static>(&mut self, out: W) {
+        self.output = Box::new(out);
+    }
+
+    //Sends the errors written by report to out instead of stderr
+    pub fn set_error_output<W: Write + 'static>(&mut self, out: W) {
+        self.error_output = Box::new(out);
+    }
+
+    //Writes the errors in a result to the error output, rendering compile errors against the
+    //source they came from
+    pub fn report(&mut self, result: &InterpretResult, source: &str) -> io::Result<()> {
+        match result {
+            InterpretResult::CompileError(errors) => {
+                for error in errors {
+                    writeln!(self.error_output, "{}", error.render(source))?;
+                }
+                Ok(())
+            }
+            InterpretResult::RuntimeError(error) => writeln!(self.error_output, "{}", error),
+            InterpretResult::Ok => Ok(()),
         }
     }
 
     pub fn interpret(&mut self, source: &str) -> InterpretResult {
-        self.chunk = match compile(source) {
-            Some(c) => c,
-            None => return InterpretResult::CompileError,
-        };
+        match compile(source, &mut self.heap) {
+            Ok(chunk) => self.run_chunk(chunk),
+            Err(errors) => InterpretResult::CompileError(errors),
+        }
+    }
+
+    //Like interpret, but the value of each top level expression statement is printed
+    pub fn interpret_repl(&mut self, source: &str) -> InterpretResult {
+        match compile_repl(source, &mut self.heap) {
+            Ok(chunk) => self.run_chunk(chunk),
+            Err(errors) => InterpretResult::CompileError(errors),
+        }
+    }
+
+    //Evaluates a single expression and returns its value. The value is not a GC root, so it must
+    //be passed to root if it is kept while anything else runs
+    pub fn evaluate(&mut self, source: &str) -> Result<Value, InterpretResult> {
+        let script = self
+            .compile_expression(source)
+            .map_err(InterpretResult::CompileError)?;
+        self.run_script(script)
+            .map_err(InterpretResult::RuntimeError)
+    }
+
+    pub fn compile_expression(&mut self, source: &str) -> Result<LoadedScript, Vec<RoxError>> {
+        let chunk = compile_expression(source, &mut self.heap)?;
+        Ok(self.keep(chunk))
+    }
+
+    //Writes the disassembly of a script along with every function in it
+    pub fn disassemble<W: Write>(&self, out: &mut W, script: &LoadedScript) -> io::Result<()> {
+        disassemble_program(out, &self.heap.get(script.function).chunk, &self.heap)
+    }
+
+    //Converts a Rust value such as f64 or &str, interning strings, for use as an argument to call
+    pub fn value_from<T: IntoValue>(&mut self, value: T) -> Value {
+        value.into_value(&mut self.heap)
+    }
+
+    //Objects are only kept alive while reachable from a script, so a value held by Rust can be
+    //freed by the next script or call that runs. Rooting it keeps it and everything it references
+    //alive until it is unrooted. Roots are counted, each call to root needs its own unroot
+    pub fn root(&mut self, value: Value) {
+        self.roots.push(value);
+    }
+
+    //Releases one root of the value, returning whether it was rooted
+    pub fn unroot(&mut self, value: Value) -> bool {
+        match self.roots.iter().rposition(|root| *root == value) {
+            Some(index) => {
+                self.roots.swap_remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    //The text `print` would write for a value
+    pub fn display(&self, value: Value) -> String {
+        value.display(&self.heap).to_string()
+    }
+
+    //Reads a global converted to a Rust type, such as f64, String or Option<bool>. Value itself
+    //can be asked for to get it unconverted
+    pub fn get_global<T: FromValue>(&self, name: &str) -> Result<T, ValueError> {
+        let value = self
+            .heap
+            .find_string(name)
+            .and_then(|name| self.globals.get(&name))
+            .ok_or_else(|| ValueError::new(&format!("Undefined variable '{}'.", name)))?;
+        T::from_value(*value, &self.heap)
+    }
+
+    //Defines or replaces a global, converting it from a Rust type such as f64, &str or Option<f64>
+    pub fn set_global<T: IntoValue>(&mut self, name: &str, value: T) {
+        let value = value.into_value(&mut self.heap);
+        let name = self.heap.intern(name);
+        self.globals.insert(name, value);
+    }
+
+    //Every global in no particular order, natives included
+    pub fn globals_iter(&self) -> impl Iterator<Item = (&str, Value)> + '_ {
+        self.globals
+            .iter()
+            .map(move |(name, value)| (self.heap.get(*name).as_str(), *value))
+    }
+
+    //Every global with the text of its value, sorted by name
+    pub fn describe_globals(&self) -> Vec<(String, String)> {
+        let mut globals: Vec<(String, String)> = self
+            .globals_iter()
+            .map(|(name, value)| (name.to_string(), self.display(value)))
+            .collect();
+        globals.sort();
+        globals
+    }
+
+    //Throws away every global and object, leaving the VM as new apart from its options
+    pub fn reset(&mut self) {
+        let mut vm = VM::new();
+        vm.heap.stress_gc = self.heap.stress_gc;
+        vm.trace_execution = self.trace_execution;
+        vm.print_code = self.print_code;
+        vm.limits = self.limits;
+        mem::swap(&mut vm.output, &mut self.output);
+        mem::swap(&mut vm.error_output, &mut self.error_output);
+        *self = vm;
+    }
+
+    //Loads a chunk written by Chunk::serialize into this VM's heap, ready for run_loaded. The
+    //bytes may come from anywhere, so the chunk is verified before it can be run
+    pub fn load(&mut self, bytes: &[u8]) -> Result<LoadedScript, BytecodeError> {
+        let chunk = Chunk::deserialize(bytes, &mut self.heap)?;
+        verify(&chunk, &self.heap)?;
+        Ok(self.keep(chunk))
+    }
+
+    //Runs a top level script chunk. The chunk must have been compiled into this VM's heap with
+    //nothing run since, as its constants are not kept alive until it starts
+    pub fn run_chunk(&mut self, chunk: Chunk) -> InterpretResult {
+        let script = self.keep(chunk);
+        self.run_loaded(script)
+    }
+
+    pub fn run_loaded(&mut self, script: LoadedScript) -> InterpretResult {
+        match self.run_script(script) {
+            Ok(_) => InterpretResult::Ok,
+            Err(error) => InterpretResult::RuntimeError(error),
+        }
+    }
 
-        self.ip = 0;
+    //Wraps a top level chunk in a function that is kept alive until it is run. Allocating never
+    //collects, so the chunk's constants can't be freed before then
+    fn keep(&mut self, chunk: Chunk) -> LoadedScript {
+        let function = self.heap.alloc(Function::new(None, 0, chunk));
+        self.scripts.push(function);
+        LoadedScript { function }
+    }
+
+    //Lets a script that won't be run be freed
+    pub fn discard(&mut self, script: LoadedScript) {
+        if let Some(index) = self.scripts.iter().rposition(|f| *f == script.function) {
+            self.scripts.swap_remove(index);
+        }
+    }
 
+    //Runs a top level script, returning the value it returns
+    fn run_script(&mut self, script: LoadedScript) -> Result<Value, RuntimeError> {
+        //Once its closure is on the stack the script no longer needs keeping
+        let function = script.function;
+        self.discard(script);
+
+        if self.print_code {
+            let chunk = &self.heap.get(function).chunk;
+            if let Err(e) = disassemble_program(&mut self.output, chunk, &self.heap) {
+                return Err(self.output_error(e));
+            }
+        }
+
+        let script = self.heap.alloc(Closure::new(function, Vec::new()));
+        self.start_limits();
+        self.push(Value::Object(ObjectType::Closure(script)));
+        self.call_closure(script, 0)?;
         self.run()
     }
 
-    fn run(&mut self) -> InterpretResult {
+    //Runs until the outermost frame returns, giving the value it returned
+    fn run(&mut self) -> Result<Value, RuntimeError> {
         loop {
-            if cfg!(debug_assertions) {
-                print!("          ");
+            //Collecting between instructions means every live object is reachable from a root,
+            //as no instruction is halfway through holding one in a local
+            if self.heap.should_collect() {
+                self.collect_garbage();
+            }
 
-                for slot in &self.stack {
-                    print!("[ ");
-                    print!("{}", slot);
-                    print!(" ]");
+            if self.trace_execution {
+                if let Err(e) = self.trace_instruction() {
+                    return Err(self.output_error(e));
                 }
-                println!();
-
-                disassemble_instruction(&self.chunk, self.ip);
             }
 
-            let instruction = OpCode::from(self.read_byte());
+            let instruction = match OpCode::try_from(self.read_byte()) {
+                Ok(instruction) => instruction,
+                Err(error) => return Err(self.runtime_error(&error.to_string())),
+            };
+            self.check_limits()?;
 
             match instruction {
-                Constant => {
-                    let constant = self.read_constant();
+                Constant | ConstantLong => {
+                    let constant = if instruction == Constant {
+                        self.read_constant()
+                    } else {
+                        self.read_constant_long()
+                    };
                     self.push(constant);
                 }
                 Nil => self.push(Value::Nil),
@@ -78,37 +394,127 @@ impl VM {
                     self.pop();
                 }
                 GetLocal => {
-                    let slot = self.read_byte() as usize;
-                    let value = self.stack[slot].clone();
-                    self.push(value);
+                    let slot = self.frame().slot_offset + self.read_byte() as usize;
+                    self.push(self.stack[slot]);
                 }
                 SetLocal => {
-                    let slot = self.read_byte() as usize;
-                    self.stack[slot] = self.peek(0).clone();
+                    let slot = self.frame().slot_offset + self.read_byte() as usize;
+                    self.stack[slot] = self.peek(0);
                 }
-                GetGlobal => {
-                    let name = self.read_constant().to_string();
+                GetGlobal | GetGlobalLong => {
+                    let name = if instruction == GetGlobal {
+                        self.read_string()
+                    } else {
+                        self.read_string_long()
+                    };
                     let value = match self.globals.get(&name) {
-                        Some(v) => v.clone(),
+                        Some(v) => *v,
                         None => {
-                            self.runtime_error(format!("Undefined variable '{}'.", name).as_str());
-                            return InterpretResult::RuntimeError;
+                            let message = format!("Undefined variable '{}'.", self.heap.get(name));
+                            return Err(self.runtime_error(&message));
                         }
                     };
                     self.push(value);
                 }
-                DefineGlobal => {
-                    let name = self.read_constant().to_string();
+                DefineGlobal | DefineGlobalLong => {
+                    let name = if instruction == DefineGlobal {
+                        self.read_string()
+                    } else {
+                        self.read_string_long()
+                    };
                     let value = self.pop();
                     self.globals.insert(name, value);
                 }
-                SetGlobal => {
-                    let name = self.read_constant().to_string();
+                SetGlobal | SetGlobalLong => {
+                    let name = if instruction == SetGlobal {
+                        self.read_string()
+                    } else {
+                        self.read_string_long()
+                    };
                     if !self.globals.contains_key(&name) {
-                        self.runtime_error(format!("Undefined variable '{}'.", name).as_str());
-                        return InterpretResult::RuntimeError;
+                        let message = format!("Undefined variable '{}'.", self.heap.get(name));
+                        return Err(self.runtime_error(&message));
+                    }
+                    self.globals.insert(name, self.peek(0));
+                }
+                GetUpvalue => {
+                    let slot = self.read_byte() as usize;
+                    let upvalue = self.heap.get(self.frame().closure).upvalues[slot];
+
+                    let value = match *self.heap.get(upvalue) {
+                        Upvalue::Open(index) => self.stack[index],
+                        Upvalue::Closed(value) => value,
+                    };
+                    self.push(value);
+                }
+                SetUpvalue => {
+                    let slot = self.read_byte() as usize;
+                    let upvalue = self.heap.get(self.frame().closure).upvalues[slot];
+                    let value = self.peek(0);
+
+                    match self.heap.get_mut(upvalue) {
+                        Upvalue::Open(index) => self.stack[*index] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    };
+                }
+                GetProperty | GetPropertyLong => {
+                    let instance = match self.peek(0) {
+                        Value::Object(ObjectType::Instance(i)) => i,
+                        _ => {
+                            return Err(self.runtime_error("Only instances have properties."));
+                        }
+                    };
+                    let name = if instruction == GetProperty {
+                        self.read_string()
+                    } else {
+                        self.read_string_long()
+                    };
+
+                    //Fields shadow methods with the same name
+                    let instance = self.heap.get(instance);
+                    match instance.fields.get(&name) {
+                        Some(value) => {
+                            let value = *value;
+                            self.pop(); //Instance
+                            self.push(value);
+                        }
+                        None => {
+                            let class = instance.class;
+                            self.bind_method(class, name)?;
+                        }
                     }
-                    self.globals.insert(name, self.peek(0).clone());
+                }
+                SetProperty | SetPropertyLong => {
+                    let instance = match self.peek(1) {
+                        Value::Object(ObjectType::Instance(i)) => i,
+                        _ => {
+                            return Err(self.runtime_error("Only instances have fields."));
+                        }
+                    };
+                    let name = if instruction == SetProperty {
+                        self.read_string()
+                    } else {
+                        self.read_string_long()
+                    };
+
+                    let value = self.pop();
+                    self.heap.get_mut(instance).fields.insert(name, value);
+
+                    self.pop(); //Instance
+                    self.push(value);
+                }
+                GetSuper | GetSuperLong => {
+                    let name = if instruction == GetSuper {
+                        self.read_string()
+                    } else {
+                        self.read_string_long()
+                    };
+                    let superclass = match self.pop() {
+                        Value::Object(ObjectType::Class(c)) => c,
+                        _ => return Err(self.runtime_error("Superclass must be a class.")),
+                    };
+
+                    self.bind_method(superclass, name)?;
                 }
                 Equal => {
                     let b = self.pop();
@@ -117,28 +523,29 @@ impl VM {
                 }
                 Greater => binary_op!(self, Value::Boolean, >),
                 Less => binary_op!(self, Value::Boolean, <),
-                Add => {
-                    if matches!(self.peek(0), Value::Object(ObjectType::String(_)))
-                        && matches!(self.peek(1), Value::Object(ObjectType::String(_)))
-                    {
-                        let b = self.pop();
-                        let a = self.pop();
-
-                        self.push(Value::Object(ObjectType::String(Box::from(
-                            a.to_string() + &b.to_string(),
-                        ))));
-                    } else if matches!(self.peek(0), Value::Number(_))
-                        && matches!(self.peek(1), Value::Number(_))
-                    {
-                        let b: f64 = self.pop().into();
-                        let a: f64 = self.pop().into();
-
+                Add => match (self.peek(1), self.peek(0)) {
+                    (
+                        Value::Object(ObjectType::String(a)),
+                        Value::Object(ObjectType::String(b)),
+                    ) => {
+                        let result = format!("{}{}", self.heap.get(a), self.heap.get(b));
+                        let result = self.heap.intern_owned(result);
+
+                        self.pop();
+                        self.pop();
+                        self.push(Value::Object(ObjectType::String(result)));
+                    }
+                    (Value::Number(a), Value::Number(b)) => {
+                        self.pop();
+                        self.pop();
                         self.push(Value::Number(a + b));
-                    } else {
-                        self.runtime_error("Operands must be two numbers or two strings.");
-                        return InterpretResult::RuntimeError;
                     }
-                }
+                    _ => {
+                        return Err(
+                            self.runtime_error("Operands must be two numbers or two strings.")
+                        );
+                    }
+                },
                 Subtract => binary_op!(self, Value::Number, -),
                 Multiple => binary_op!(self, Value::Number, *),
                 Divide => binary_op!(self, Value::Number, /),
@@ -147,159 +554,1063 @@ impl VM {
                     self.push(Value::Boolean(value));
                 }
                 Negate => match self.peek(0) {
-                    Value::Number(_) => {
-                        let n: f64 = self.pop().into();
+                    Value::Number(n) => {
+                        self.pop();
                         self.push(Value::Number(-n))
                     }
                     _ => {
-                        self.runtime_error("Operand must be a number.");
-                        return InterpretResult::RuntimeError;
+                        return Err(self.runtime_error("Operand must be a number."));
                     }
                 },
                 Print => {
-                    println!("{}", self.pop());
+                    let value = self.pop();
+                    if let Err(e) = writeln!(self.output, "{}", value.display(&self.heap)) {
+                        return Err(self.output_error(e));
+                    }
                 }
                 Jump => {
                     let offset = self.read_short() as usize;
-                    self.ip += offset;
+                    self.frame_mut().ip += offset;
                 }
                 JumpIfFalse => {
                     let offset = self.read_short() as usize;
 
                     if self.peek(0).is_falsey() {
-                        self.ip += offset;
+                        self.frame_mut().ip += offset;
                     }
                 }
                 Loop => {
                     let offset = self.read_short() as usize;
-                    self.ip -= offset;
+                    self.frame_mut().ip -= offset;
+                }
+                Call => {
+                    let arg_count = self.read_byte() as usize;
+                    let callee = self.peek(arg_count);
+
+                    self.call_value(callee, arg_count)?;
+                }
+                Closure | ClosureLong => {
+                    let function = if instruction == Closure {
+                        self.read_constant()
+                    } else {
+                        self.read_constant_long()
+                    };
+                    let function = match function {
+                        Value::Object(ObjectType::Function(f)) => f,
+                        _ => unreachable!("Closure operand must be a function"),
+                    };
+                    let upvalue_count = self.heap.get(function).upvalue_count;
+                    let mut upvalues = Vec::with_capacity(upvalue_count);
+
+                    for _ in 0..upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+
+                        let upvalue = if is_local {
+                            self.capture_upvalue(self.frame().slot_offset + index)
+                        } else {
+                            self.heap.get(self.frame().closure).upvalues[index]
+                        };
+                        upvalues.push(upvalue);
+                    }
+
+                    let closure = self.heap.alloc(Closure::new(function, upvalues));
+                    self.push(Value::Object(ObjectType::Closure(closure)));
+                }
+                CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                Class | ClassLong => {
+                    let name = if instruction == Class {
+                        self.read_string()
+                    } else {
+                        self.read_string_long()
+                    };
+                    let name = self.heap.get(name).clone();
+                    let class = self.heap.alloc(Class::new(name));
+                    self.push(Value::Object(ObjectType::Class(class)));
+                }
+                Inherit => {
+                    let superclass = match self.peek(1) {
+                        Value::Object(ObjectType::Class(c)) => c,
+                        _ => {
+                            return Err(self.runtime_error("Superclass must be a class."));
+                        }
+                    };
+
+                    //Methods are copied down when the subclass is created, so later overrides win
+                    if let Value::Object(ObjectType::Class(subclass)) = self.peek(0) {
+                        let methods = self.heap.get(superclass).methods.clone();
+                        self.heap.get_mut(subclass).methods.extend(methods);
+                    }
+                    self.pop(); //Subclass
+                }
+                Method | MethodLong => {
+                    let name = if instruction == Method {
+                        self.read_string()
+                    } else {
+                        self.read_string_long()
+                    };
+                    self.define_method(name)?;
                 }
                 Return => {
-                    return InterpretResult::Ok;
+                    let result = self.pop();
+                    let frame = self.frames.pop().unwrap();
+
+                    self.close_upvalues(frame.slot_offset);
+
+                    //Discard the arguments and the function itself
+                    self.stack.truncate(frame.slot_offset);
+
+                    if self.frames.is_empty() {
+                        return Ok(result);
+                    }
+
+                    self.push(result);
                 }
             };
         }
     }
 
-    fn read_byte(&mut self) -> u8 {
-        let byte = self.chunk.code[self.ip];
-        self.ip += 1;
-        byte
+    //Calls a function, class or method from Rust and runs it to completion, giving its return
+    //value. The callee is either a value or the name of a global holding one. Like evaluate, the
+    //result is not a GC root and has to be passed to root to be kept while anything else runs
+    pub fn call<'a, C: Into<Callee<'a>>>(
+        &mut self,
+        callee: C,
+        args: &[Value],
+    ) -> Result<Value, RuntimeError> {
+        let callee = match callee.into() {
+            Callee::Value(value) => value,
+            Callee::Name(name) => match self.get_global::<Value>(name) {
+                Ok(value) => value,
+                Err(error) => return Err(self.runtime_error(&error.message)),
+            },
+        };
+
+        self.start_limits();
+        self.push(callee);
+        for arg in args {
+            self.push(*arg);
+        }
+        self.call_value(callee, args.len())?;
+
+        //Natives and classes without an initializer are done without pushing a frame
+        if self.frames.is_empty() {
+            Ok(self.pop())
+        } else {
+            self.run()
+        }
+    }
+
+    fn start_limits(&mut self) {
+        self.instructions_run = 0;
+        self.deadline = self.limits.time.map(|time| Instant::now() + time);
+    }
+
+    //Called once per instruction, the stack can grow by at most a few values in between
+    fn check_limits(&mut self) -> Result<(), RuntimeError> {
+        self.instructions_run += 1;
+
+        if self
+            .limits
+            .instructions
+            .is_some_and(|limit| self.instructions_run > limit)
+        {
+            return Err(self.runtime_error("Instruction limit exceeded."));
+        }
+
+        if self.instructions_run.is_multiple_of(TIME_CHECK_INTERVAL)
+            && self.deadline.is_some_and(|d| Instant::now() >= d)
+        {
+            return Err(self.runtime_error("Time limit exceeded."));
+        }
+
+        if self.stack.len() > self.limits.stack {
+            return Err(self.runtime_error("Value stack limit exceeded."));
+        }
+
+        Ok(())
+    }
+
+    //Writes the stack and the instruction about to run
+    fn trace_instruction(&mut self) -> io::Result<()> {
+        write!(self.output, "          ")?;
+        for slot in &self.stack {
+            write!(self.output, "[ {} ]", slot.display(&self.heap))?;
+        }
+        writeln!(self.output)?;
+
+        let frame = self.frames.last().unwrap();
+        let chunk = &self.heap.get(frame.function).chunk;
+        disassemble_instruction(&mut self.output, chunk, &self.heap, frame.ip)?;
+        Ok(())
+    }
+
+    fn output_error(&mut self, error: io::Error) -> RuntimeError {
+        self.runtime_error(&format!("Could not write output.\n{}", error))
+    }
+
+    fn call_value(&mut self, callee: Value, arg_count: usize) -> Result<(), RuntimeError> {
+        match callee {
+            Value::Object(ObjectType::Closure(c)) => self.call_closure(c, arg_count),
+            Value::Object(ObjectType::Class(class)) => {
+                //The new instance replaces the class in the callee slot, becoming `this`
+                let instance = self.heap.alloc(Instance::new(class));
+                let slot = self.stack.len() - arg_count - 1;
+                self.stack[slot] = Value::Object(ObjectType::Instance(instance));
+
+                let initializer = self.heap.get(class).methods.get(&self.init_string).copied();
+                match initializer {
+                    Some(init) => self.call_closure(init, arg_count),
+                    None if arg_count != 0 => Err(self.runtime_error(
+                        format!("Expected 0 arguments but got {}.", arg_count).as_str(),
+                    )),
+                    None => Ok(()),
+                }
+            }
+            Value::Object(ObjectType::BoundMethod(bound)) => {
+                let bound = self.heap.get(bound);
+                let (receiver, method) = (bound.receiver, bound.method);
+
+                let slot = self.stack.len() - arg_count - 1;
+                self.stack[slot] = receiver;
+                self.call_closure(method, arg_count)
+            }
+            Value::Object(ObjectType::Native(native)) => {
+                let native = self.heap.get(native);
+                let (arity, function) = (native.arity, native.function);
+
+                if arg_count != arity {
+                    return Err(self.runtime_error(
+                        format!("Expected {} arguments but got {}.", arity, arg_count).as_str(),
+                    ));
+                }
+
+                let args_start = self.stack.len() - arg_count;
+                match function(&mut self.heap, &self.stack[args_start..]) {
+                    Ok(result) => {
+                        //Discard the arguments and the native itself
+                        self.stack.truncate(args_start - 1);
+                        self.push(result);
+                        Ok(())
+                    }
+                    Err(message) => Err(self.runtime_error(&message)),
+                }
+            }
+            _ => Err(self.runtime_error("Can only call functions and classes.")),
+        }
+    }
+
+    fn call_closure(&mut self, closure: Gc<Closure>, arg_count: usize) -> Result<(), RuntimeError> {
+        let function = self.heap.get(closure).function;
+        let arity = self.heap.get(function).arity;
+
+        if arg_count != arity {
+            return Err(self.runtime_error(
+                format!("Expected {} arguments but got {}.", arity, arg_count).as_str(),
+            ));
+        }
+
+        if self.frames.len() >= self.limits.call_depth {
+            return Err(self.runtime_error("Stack overflow."));
+        }
+
+        self.frames.push(CallFrame {
+            closure,
+            function,
+            ip: 0,
+            slot_offset: self.stack.len() - arg_count - 1,
+        });
+        Ok(())
+    }
+
+    fn define_method(&mut self, name: Gc<String>) -> Result<(), RuntimeError> {
+        let method = match self.peek(0) {
+            Value::Object(ObjectType::Closure(c)) => c,
+            _ => return Err(self.runtime_error("Method must be a closure.")),
+        };
+
+        if let Value::Object(ObjectType::Class(class)) = self.peek(1) {
+            self.heap.get_mut(class).methods.insert(name, method);
+        }
+
+        self.pop();
+        Ok(())
+    }
+
+    fn bind_method(&mut self, class: Gc<Class>, name: Gc<String>) -> Result<(), RuntimeError> {
+        let method = match self.heap.get(class).methods.get(&name) {
+            Some(m) => *m,
+            None => {
+                let message = format!("Undefined property '{}'.", self.heap.get(name));
+                return Err(self.runtime_error(&message));
+            }
+        };
+
+        let bound = BoundMethod {
+            receiver: self.pop(),
+            method,
+        };
+        let bound = self.heap.alloc(bound);
+        self.push(Value::Object(ObjectType::BoundMethod(bound)));
+        Ok(())
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Gc<Upvalue> {
+        //Reuse an existing upvalue so every closure sees the same variable
+        for upvalue in &self.open_upvalues {
+            if *self.heap.get(*upvalue) == Upvalue::Open(slot) {
+                return *upvalue;
+            }
+        }
+
+        let upvalue = self.heap.alloc(Upvalue::Open(slot));
+        self.open_upvalues.push(upvalue);
+        upvalue
+    }
+
+    fn close_upvalues(&mut self, last: usize) {
+        let stack = &self.stack;
+        let heap = &mut self.heap;
+
+        self.open_upvalues.retain(|upvalue| {
+            let upvalue = heap.get_mut(*upvalue);
+            let slot = match *upvalue {
+                Upvalue::Open(slot) => slot,
+                Upvalue::Closed(_) => return false,
+            };
+
+            if slot >= last {
+                *upvalue = Upvalue::Closed(stack[slot]);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    fn collect_garbage(&mut self) {
+        for value in &self.stack {
+            self.heap.mark_value(*value);
+        }
+
+        for frame in &self.frames {
+            self.heap.mark(frame.closure);
+        }
+
+        for upvalue in &self.open_upvalues {
+            self.heap.mark(*upvalue);
+        }
+
+        for value in &self.roots {
+            self.heap.mark_value(*value);
+        }
+
+        for function in &self.scripts {
+            self.heap.mark(*function);
+        }
+
+        for (name, value) in &self.globals {
+            self.heap.mark(*name);
+            self.heap.mark_value(*value);
+        }
+
+        self.heap.mark(self.init_string);
+
+        self.heap.collect();
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().unwrap()
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frames.last_mut().unwrap();
+        let byte = self.heap.get(frame.function).chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_constant(&mut self) -> Value {
+        let index = self.read_byte() as usize;
+        self.heap.get(self.frame().function).chunk.constants[index]
+    }
+
+    //24-bit big-endian index used by the long instruction variants
+    fn read_constant_long(&mut self) -> Value {
+        let index = (self.read_byte() as usize) << 16
+            | (self.read_byte() as usize) << 8
+            | self.read_byte() as usize;
+        self.heap.get(self.frame().function).chunk.constants[index]
+    }
+
+    fn read_string(&mut self) -> Gc<String> {
+        match self.read_constant() {
+            Value::Object(ObjectType::String(s)) => s,
+            _ => unreachable!("Operand must be a string"),
+        }
+    }
+
+    fn read_string_long(&mut self) -> Gc<String> {
+        match self.read_constant_long() {
+            Value::Object(ObjectType::String(s)) => s,
+            _ => unreachable!("Operand must be a string"),
+        }
+    }
+
+    fn read_short(&mut self) -> u16 {
+        let frame = self.frames.last_mut().unwrap();
+        frame.ip += 2;
+
+        let code = &self.heap.get(frame.function).chunk.code;
+        u16::from_be_bytes([code[frame.ip - 2], code[frame.ip - 1]])
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap()
+    }
+
+    fn peek(&self, distance: usize) -> Value {
+        self.stack[(self.stack.len() - 1) - distance]
+    }
+
+    fn runtime_error(&mut self, message: &str) -> RuntimeError {
+        //Innermost frame first, each pointing at the instruction that was executing. A call from
+        //Rust can fail before there is any frame
+        let trace = self
+            .frames
+            .iter()
+            .rev()
+            .map(|frame| {
+                let function = self.heap.get(frame.function);
+                TraceFrame {
+                    function: function.name.clone(),
+                    line: function.chunk.get_line(frame.ip - 1),
+                }
+            })
+            .collect();
+
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+
+        RuntimeError::new(message, trace)
+    }
+}
+
+//A top level script compiled or loaded into a VM, which keeps it and its constants alive until it
+//is run. One that is never run stays alive until it is discarded or the VM is reset
+pub struct LoadedScript {
+    function: Gc<Function>,
+}
+
+//What VM::call calls, a function value or the name of a global holding one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Callee<'a> {
+    Name(&'a str),
+    Value(Value),
+}
+
+impl<'a> From<&'a str> for Callee<'a> {
+    fn from(name: &'a str) -> Self {
+        Callee::Name(name)
+    }
+}
+
+impl From<Value> for Callee<'_> {
+    fn from(value: Value) -> Self {
+        Callee::Value(value)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum InterpretResult {
+    Ok,
+    CompileError(Vec<RoxError>),
+    RuntimeError(RuntimeError),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TraceFrame {
+    pub function: Option<String>, //None for the top level script
+    pub line: i32,
+}
+
+impl Display for TraceFrame {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match &self.function {
+            Some(name) => write!(f, "[line {}] in {}()", self.line, name),
+            None => write!(f, "[line {}] in script", self.line),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    pub line: i32, //Line of the instruction that failed, 0 if it failed before one ran
+    pub trace: Vec<TraceFrame>,
+}
+
+impl RuntimeError {
+    pub fn new(message: &str, trace: Vec<TraceFrame>) -> Self {
+        RuntimeError {
+            message: message.to_string(),
+            line: trace.first().map_or(0, |frame| frame.line),
+            trace,
+        }
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        for frame in &self.trace {
+            write!(f, "\n{}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for RuntimeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::output::Buffer;
+    use crate::Span;
+
+    fn frame_with_chunk(vm: &mut VM, chunk: Chunk) -> CallFrame {
+        let function = vm.heap.alloc(Function::new(None, 0, chunk));
+        CallFrame {
+            closure: vm.heap.alloc(Closure::new(function, Vec::new())),
+            function,
+            ip: 0,
+            slot_offset: 0,
+        }
+    }
+
+    #[test]
+    fn vm_push() {
+        let mut vm = VM::new();
+
+        vm.push(Value::Number(1.0));
+
+        assert_eq!(vm.stack.len(), 1);
+        assert_eq!(vm.stack[0], Value::Number(1.0));
+    }
+
+    #[test]
+    fn vm_pop() {
+        let mut vm = VM::new();
+        vm.push(Value::Number(1.0));
+
+        let result = vm.pop();
+
+        assert_eq!(vm.stack.len(), 0);
+        assert_eq!(result, Value::Number(1.0));
+    }
+
+    #[test]
+    fn vm_read_byte() {
+        let mut vm = VM::new();
+        let frame = frame_with_chunk(
+            &mut vm,
+            Chunk {
+                code: vec![0],
+                constants: Vec::new(),
+                lines: Vec::new(),
+            },
+        );
+        vm.frames.push(frame);
+
+        let result = vm.read_byte();
+
+        assert_eq!(result, 0);
+    }
+
+    #[test]
+    fn vm_read_constant() {
+        let mut vm = VM::new();
+        let frame = frame_with_chunk(
+            &mut vm,
+            Chunk {
+                code: vec![0],
+                constants: vec![Value::Number(1.0)],
+                lines: Vec::new(),
+            },
+        );
+        vm.frames.push(frame);
+
+        let result = vm.read_constant();
+
+        assert_eq!(result, Value::Number(1.0));
+    }
+
+    #[test]
+    fn vm_read_constant_long() {
+        let mut vm = VM::new();
+        let mut constants = vec![Value::Nil; 0x0102];
+        constants.push(Value::Number(1.0));
+        let frame = frame_with_chunk(
+            &mut vm,
+            Chunk {
+                code: vec![0x00, 0x01, 0x02],
+                constants,
+                lines: Vec::new(),
+            },
+        );
+        vm.frames.push(frame);
+
+        let result = vm.read_constant_long();
+
+        assert_eq!(result, Value::Number(1.0));
+        assert_eq!(vm.frame().ip, 3);
+    }
+
+    #[test]
+    fn vm_read_short() {
+        let mut vm = VM::new();
+        let frame = frame_with_chunk(
+            &mut vm,
+            Chunk {
+                code: vec![255, 1],
+                constants: Vec::new(),
+                lines: Vec::new(),
+            },
+        );
+        vm.frames.push(frame);
+
+        let result = vm.read_short();
+
+        assert_eq!(result, 65281);
+    }
+
+    #[test]
+    fn vm_interpret_compile_error() {
+        let mut vm = VM::new();
+
+        let result = vm.interpret("+1");
+
+        match result {
+            InterpretResult::CompileError(errors) => {
+                assert_eq!(
+                    errors,
+                    vec![RoxError::new(
+                        "Expect expression.",
+                        "+".to_string(),
+                        Span::new(0, 1, 1, 1)
+                    )]
+                )
+            }
+            _ => panic!("Expected a compile error"),
+        }
+    }
+
+    #[test]
+    fn vm_evaluate() {
+        let mut vm = VM::new();
+        vm.interpret("var a = \"x\";");
+
+        let result = vm.evaluate("a + \"y\"").unwrap();
+
+        assert_eq!(vm.display(result), "xy");
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn vm_evaluate_runtime_error() {
+        let mut vm = VM::new();
+
+        let result = vm.evaluate("-nil");
+
+        match result {
+            Err(InterpretResult::RuntimeError(error)) => {
+                assert_eq!(error.message, "Operand must be a number.")
+            }
+            _ => panic!("Expected a runtime error"),
+        }
+    }
+
+    #[test]
+    fn vm_describe_globals() {
+        let mut vm = VM::new();
+        vm.interpret("var b = 2; var a = \"1\";");
+
+        let result = vm.describe_globals();
+
+        assert_eq!(result[0], ("a".to_string(), "1".to_string()));
+        assert_eq!(result[1], ("b".to_string(), "2".to_string()));
+    }
+
+    #[test]
+    fn vm_reset() {
+        let mut vm = VM::new();
+        vm.set_trace_execution(true);
+        vm.interpret("var a = 1;");
+
+        vm.reset();
+
+        assert!(vm.trace_execution);
+        assert!(vm.evaluate("a").is_err());
+        assert!(vm.evaluate("clock").is_ok());
+    }
+
+    #[test]
+    fn vm_set_global() {
+        let mut vm = VM::new();
+        vm.set_global("a", 2.0);
+        vm.set_global("b", "x");
+        vm.set_global("c", None::<f64>);
+
+        vm.interpret("var d = str(a * 2) + b; var e = c == nil;");
+
+        assert_eq!(vm.get_global::<String>("d"), Ok("4x".to_string()));
+        assert_eq!(vm.get_global::<bool>("e"), Ok(true));
+    }
+
+    #[test]
+    fn vm_get_global_errors() {
+        let mut vm = VM::new();
+        vm.interpret("var a = 1;");
+
+        assert_eq!(
+            vm.get_global::<f64>("b"),
+            Err(ValueError::new("Undefined variable 'b'."))
+        );
+        assert_eq!(
+            vm.get_global::<bool>("a"),
+            Err(ValueError::new("Expected a boolean but got number."))
+        );
+        assert_eq!(vm.get_global::<Value>("a"), Ok(Value::Number(1.0)));
+    }
+
+    #[test]
+    fn vm_globals_iter() {
+        let mut vm = VM::new();
+        vm.set_global("a", true);
+
+        let result: Vec<(&str, Value)> = vm.globals_iter().filter(|(n, _)| *n == "a").collect();
+
+        assert_eq!(result, vec![("a", Value::Boolean(true))]);
+        assert_eq!(vm.globals_iter().count(), 7);
+    }
+
+    #[test]
+    fn vm_call_by_name() {
+        let mut vm = VM::new();
+        vm.interpret("var total = 0; fun add(a, b) { total = total + a + b; return total; }");
+
+        vm.call("add", &[Value::Number(1.0), Value::Number(2.0)])
+            .unwrap();
+        let result = vm.call("add", &[Value::Number(3.0), Value::Number(4.0)]);
+
+        assert_eq!(result, Ok(Value::Number(10.0)));
+        assert!(vm.stack.is_empty());
+        assert!(vm.frames.is_empty());
+    }
+
+    #[test]
+    fn vm_call_value() {
+        let mut vm = VM::new();
+        vm.interpret(
+            "class Counter { init(n) { this.n = n; } next() { this.n = this.n + 1; return this.n; } }",
+        );
+
+        let counter = vm.call("Counter", &[Value::Number(5.0)]).unwrap();
+        vm.set_global("counter", counter);
+        let next = vm.evaluate("counter.next").unwrap();
+        let result = vm.call(next, &[]);
+
+        assert_eq!(result, Ok(Value::Number(6.0)));
+    }
+
+    #[test]
+    fn vm_call_native() {
+        let mut vm = VM::new();
+
+        let result = vm.call("type", &[Value::Nil]).unwrap();
+
+        assert_eq!(vm.display(result), "nil");
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn vm_call_with_converted_args() {
+        let mut vm = VM::new();
+        vm.interpret("fun greet(name, times) { return name + str(times); }");
+
+        let args = [vm.value_from("rox"), vm.value_from(2.0)];
+        let result = vm.call("greet", &args).unwrap();
+
+        assert_eq!(vm.display(result), "rox2");
+    }
+
+    #[test]
+    fn vm_root_keeps_value_alive() {
+        let mut vm = VM::new();
+        vm.set_stress_gc(true);
+        vm.interpret("class Point { init() { this.x = \"x\"; } } fun make() { return Point(); }");
+
+        let point = vm.call("make", &[]).unwrap();
+        vm.root(point);
+        vm.interpret("var s = \"a\" + \"b\";");
+
+        assert_eq!(vm.display(point), "Point instance");
+        vm.set_global("p", point);
+        let x = vm.evaluate("p.x").unwrap();
+        assert_eq!(vm.display(x), "x");
+        assert!(vm.unroot(point));
+        assert!(!vm.unroot(point));
     }
 
-    fn read_constant(&mut self) -> Value {
-        let index = self.read_byte() as usize;
-        self.chunk.constants[index].clone()
+    #[test]
+    fn vm_call_errors() {
+        let mut vm = VM::new();
+        vm.interpret("fun f(a) { return -a; }");
+
+        let undefined = vm.call("g", &[]).unwrap_err();
+        let arity = vm.call("f", &[]).unwrap_err();
+        let runtime = vm.call("f", &[Value::Nil]).unwrap_err();
+        let not_callable = vm.call(Value::Number(1.0), &[]).unwrap_err();
+
+        assert_eq!(undefined.message, "Undefined variable 'g'.");
+        assert_eq!(arity.message, "Expected 1 arguments but got 0.");
+        assert_eq!(runtime.message, "Operand must be a number.");
+        assert_eq!(runtime.trace.len(), 1);
+        assert_eq!(not_callable.message, "Can only call functions and classes.");
+        assert!(vm.stack.is_empty());
+        assert_eq!(vm.call("f", &[Value::Number(2.0)]), Ok(Value::Number(-2.0)));
     }
 
-    fn read_short(&mut self) -> u16 {
-        self.ip += 2;
+    #[test]
+    fn vm_print_to_output() {
+        let mut vm = VM::new();
+        let output = Buffer::new();
+        vm.set_output(output.clone());
 
-        u16::from_be_bytes([self.chunk.code[self.ip - 2], self.chunk.code[self.ip - 1]])
+        vm.interpret("print 1; print \"a\" + \"b\";");
+
+        assert_eq!(output.contents(), "1\nab\n");
     }
 
-    fn push(&mut self, value: Value) {
-        self.stack.push(value);
+    #[test]
+    fn vm_trace_to_output() {
+        let mut vm = VM::new();
+        let output = Buffer::new();
+        vm.set_output(output.clone());
+        vm.set_trace_execution(true);
+
+        vm.interpret("print nil;");
+
+        assert_eq!(
+            output.contents(),
+            "          [ <script> ]
+0000    1 NIL
+          [ <script> ][ nil ]
+0001    | PRINT
+nil
+          [ <script> ]
+0002    | NIL
+          [ <script> ][ nil ]
+0003    | RETURN
+"
+        );
     }
 
-    fn pop(&mut self) -> Value {
-        self.stack.pop().unwrap()
+    #[test]
+    fn vm_report_to_error_output() {
+        let mut vm = VM::new();
+        let errors = Buffer::new();
+        vm.set_error_output(errors.clone());
+
+        let source = "print -nil;";
+        let result = vm.interpret(source);
+        vm.report(&result, source).unwrap();
+        let source = "print;";
+        let result = vm.interpret(source);
+        vm.report(&result, source).unwrap();
+
+        assert_eq!(
+            errors.contents(),
+            "Operand must be a number.
+[line 1] in script
+[line 1] Error at ';': Expect expression.
+1 | print;
+  |      ^
+"
+        );
+    }
+
+    #[test]
+    fn vm_reset_keeps_output() {
+        let mut vm = VM::new();
+        let output = Buffer::new();
+        vm.set_output(output.clone());
+
+        vm.reset();
+        vm.interpret("print 2;");
+
+        assert_eq!(output.contents(), "2\n");
     }
 
-    fn peek(&self, distance: usize) -> &Value {
-        &self.stack[(self.stack.len() - 1) - distance]
+    fn runtime_error_message(result: InterpretResult) -> String {
+        match result {
+            InterpretResult::RuntimeError(error) => error.message,
+            _ => panic!("Expected a runtime error"),
+        }
     }
 
-    fn runtime_error(&mut self, message: &str) {
-        eprintln!(
-            "{}\n[line {}] in script",
-            message, self.chunk.lines[self.ip]
+    #[test]
+    fn vm_instruction_limit() {
+        let mut vm = VM::new();
+        vm.set_limits(Limits {
+            instructions: Some(1000),
+            ..Limits::default()
+        });
+
+        let result = vm.interpret("while (true) {}");
+
+        assert_eq!(runtime_error_message(result), "Instruction limit exceeded.");
+        assert_eq!(
+            vm.interpret("var i = 0; while (i < 10) i = i + 1;"),
+            InterpretResult::Ok
         );
-        self.stack.clear();
     }
-}
 
-#[derive(Debug, PartialEq)]
-pub enum InterpretResult {
-    Ok,
-    CompileError,
-    RuntimeError,
-}
+    #[test]
+    fn vm_time_limit() {
+        let mut vm = VM::new();
+        vm.set_limits(Limits {
+            time: Some(Duration::from_millis(10)),
+            ..Limits::default()
+        });
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let result = vm.interpret("while (true) {}");
+
+        assert_eq!(runtime_error_message(result), "Time limit exceeded.");
+    }
 
     #[test]
-    fn vm_push() {
+    fn vm_stack_limit() {
         let mut vm = VM::new();
+        vm.set_limits(Limits {
+            stack: 3,
+            ..Limits::default()
+        });
 
-        vm.push(Value::Number(1.0));
+        let result = vm.interpret("print 1 + (2 + (3 + 4));");
 
-        assert_eq!(vm.stack.len(), 1);
-        assert_eq!(vm.stack[0], Value::Number(1.0));
+        assert_eq!(runtime_error_message(result), "Value stack limit exceeded.");
+        assert!(vm.stack.is_empty());
     }
 
     #[test]
-    fn vm_pop() {
+    fn vm_call_depth_limit() {
         let mut vm = VM::new();
-        vm.push(Value::Number(1.0));
+        vm.set_limits(Limits {
+            call_depth: 10,
+            ..Limits::default()
+        });
 
-        let result = vm.pop();
+        let result = vm.interpret("fun f() { f(); } f();");
 
-        assert_eq!(vm.stack.len(), 0);
-        assert_eq!(result, Value::Number(1.0));
+        match result {
+            InterpretResult::RuntimeError(error) => {
+                assert_eq!(error.message, "Stack overflow.");
+                assert_eq!(error.trace.len(), 10);
+            }
+            _ => panic!("Expected a runtime error"),
+        }
     }
 
     #[test]
-    fn vm_read_byte() {
+    fn vm_call_limits() {
         let mut vm = VM::new();
-        vm.chunk = Chunk {
-            code: vec![0],
-            constants: Vec::new(),
-            lines: Vec::new(),
-        };
+        vm.interpret("fun spin() { while (true) {} }");
+        vm.set_limits(Limits {
+            instructions: Some(100),
+            ..Limits::default()
+        });
 
-        let result = vm.read_byte();
+        let result = vm.call("spin", &[]).unwrap_err();
 
-        assert_eq!(result, 0);
+        assert_eq!(result.message, "Instruction limit exceeded.");
     }
 
     #[test]
-    fn vm_read_constant() {
+    fn vm_run_loaded_chunk() {
+        let mut heap = Heap::new();
+        let bytes = compile("var a = \"x\" + \"y\";", &mut heap)
+            .unwrap()
+            .serialize(&heap)
+            .unwrap();
         let mut vm = VM::new();
-        vm.chunk = Chunk {
-            code: vec![0],
-            constants: vec![Value::Number(1.0)],
-            lines: Vec::new(),
-        };
 
-        let result = vm.read_constant();
+        let script = vm.load(&bytes).unwrap();
+        let result = vm.run_loaded(script);
 
-        assert_eq!(result, Value::Number(1.0));
+        assert_eq!(result, InterpretResult::Ok);
+        let name = vm.heap.intern("a");
+        let xy = vm.heap.intern("xy");
+        assert_eq!(vm.globals[&name], Value::Object(ObjectType::String(xy)));
     }
 
     #[test]
-    fn vm_read_short() {
+    fn vm_loaded_script_survives_collection() {
+        let mut heap = Heap::new();
+        let bytes = compile("print \"x\" + \"y\";", &mut heap)
+            .unwrap()
+            .serialize(&heap)
+            .unwrap();
         let mut vm = VM::new();
-        vm.chunk = Chunk {
-            code: vec![255, 1],
-            constants: Vec::new(),
-            lines: Vec::new(),
-        };
+        let output = Buffer::new();
+        vm.set_output(output.clone());
+        vm.set_stress_gc(true);
 
-        let result = vm.read_short();
+        let script = vm.load(&bytes).unwrap();
+        let expression = vm.compile_expression("\"z\"").unwrap();
+        vm.interpret("for (var i = 0; i < 3; i = i + 1) { var s = \"a\" + str(i); }");
+        let result = vm.run_loaded(script);
+        let mut disassembly = Vec::new();
+        vm.disassemble(&mut disassembly, &expression).unwrap();
 
-        assert_eq!(result, 65281);
+        assert_eq!(result, InterpretResult::Ok);
+        assert_eq!(output.take(), "xy\n");
+        assert!(String::from_utf8(disassembly)
+            .unwrap()
+            .contains("CONSTANT            0 z"));
+        assert_eq!(vm.scripts.len(), 1);
+        vm.discard(expression);
+        assert!(vm.scripts.is_empty());
     }
 
     #[test]
-    fn vm_interpret_compile_error() {
+    fn vm_run_unknown_opcode() {
         let mut vm = VM::new();
+        let mut chunk = Chunk::new();
+        chunk.write(200, 3);
 
-        let result = vm.interpret("+1");
+        let result = vm.run_chunk(chunk);
 
-        assert_eq!(result, InterpretResult::CompileError);
+        match result {
+            InterpretResult::RuntimeError(error) => {
+                assert_eq!(error.message, "Unknown opcode 200.");
+                assert_eq!(error.line, 3);
+            }
+            _ => panic!("Expected a runtime error"),
+        }
     }
 
     #[test]
@@ -326,7 +1637,7 @@ mod tests {
 
         let result = vm.interpret("{");
 
-        assert_eq!(result, InterpretResult::CompileError);
+        assert!(matches!(result, InterpretResult::CompileError(_)));
     }
 
     #[test]
@@ -335,7 +1646,7 @@ mod tests {
 
         let result = vm.interpret("{var a; var a;}");
 
-        assert_eq!(result, InterpretResult::CompileError);
+        assert!(matches!(result, InterpretResult::CompileError(_)));
     }
 
     #[test]
@@ -344,7 +1655,7 @@ mod tests {
 
         let result = vm.interpret("{var a = a;}");
 
-        assert_eq!(result, InterpretResult::CompileError);
+        assert!(matches!(result, InterpretResult::CompileError(_)));
     }
 
     #[test]
@@ -371,7 +1682,7 @@ mod tests {
 
         let result = vm.interpret("var a");
 
-        assert_eq!(result, InterpretResult::CompileError);
+        assert!(matches!(result, InterpretResult::CompileError(_)));
     }
 
     #[test]
@@ -380,7 +1691,7 @@ mod tests {
 
         let result = vm.interpret("var = 1;");
 
-        assert_eq!(result, InterpretResult::CompileError);
+        assert!(matches!(result, InterpretResult::CompileError(_)));
     }
 
     #[test]
@@ -398,7 +1709,7 @@ mod tests {
 
         let result = vm.interpret("a;");
 
-        assert_eq!(result, InterpretResult::RuntimeError);
+        assert!(matches!(result, InterpretResult::RuntimeError(_)));
     }
 
     #[test]
@@ -410,13 +1721,47 @@ mod tests {
         assert_eq!(result, InterpretResult::Ok);
     }
 
+    #[test]
+    fn vm_interpret_long_globals() {
+        let mut vm = VM::new();
+        let source: String = (0..300).map(|i| format!("var a{} = {};", i, i)).collect();
+
+        let result = vm.interpret(&(source + "a299 = a299 + a0 + 1;"));
+
+        assert_eq!(result, InterpretResult::Ok);
+        let name = vm.heap.intern("a299");
+        assert_eq!(vm.globals[&name], Value::Number(300.0));
+    }
+
+    #[test]
+    fn vm_interpret_long_functions_and_classes() {
+        let mut vm = VM::new();
+        let output = Buffer::new();
+        vm.set_output(output.clone());
+        let source: String = (0..300).map(|i| format!("var a{} = {};", i, i)).collect();
+
+        let result = vm.interpret(
+            &(source
+                + "fun f() { var x = 1; fun g() { return x; } return g; }
+                class A { name() { return \"a\"; } }
+                class B < A { name() { return super.name() + \"b\"; } }
+                var c = B();
+                c.x = 5;
+                print f()() + c.x;
+                print c.name();"),
+        );
+
+        assert_eq!(result, InterpretResult::Ok);
+        assert_eq!(output.take(), "6\nab\n");
+    }
+
     #[test]
     fn vm_interpret_set_global_undefined() {
         let mut vm = VM::new();
 
         let result = vm.interpret("a = 1;");
 
-        assert_eq!(result, InterpretResult::RuntimeError);
+        assert!(matches!(result, InterpretResult::RuntimeError(_)));
     }
 
     #[test]
@@ -425,7 +1770,7 @@ mod tests {
 
         let result = vm.interpret("true = 1;");
 
-        assert_eq!(result, InterpretResult::CompileError);
+        assert!(matches!(result, InterpretResult::CompileError(_)));
     }
 
     #[test]
@@ -443,7 +1788,7 @@ mod tests {
 
         let result = vm.interpret("-false;");
 
-        assert_eq!(result, InterpretResult::RuntimeError);
+        assert!(matches!(result, InterpretResult::RuntimeError(_)));
     }
 
     #[test]
@@ -516,10 +1861,10 @@ mod tests {
         let mut vm = VM::new();
 
         let result = vm.interpret("1 + true;");
-        assert_eq!(result, InterpretResult::RuntimeError);
+        assert!(matches!(result, InterpretResult::RuntimeError(_)));
 
         let result = vm.interpret("false / 0;");
-        assert_eq!(result, InterpretResult::RuntimeError);
+        assert!(matches!(result, InterpretResult::RuntimeError(_)));
     }
 
     #[test]
@@ -546,7 +1891,7 @@ mod tests {
 
         let result = vm.interpret("\"hello\" + 123;");
 
-        assert_eq!(result, InterpretResult::RuntimeError);
+        assert!(matches!(result, InterpretResult::RuntimeError(_)));
     }
 
     #[test]
@@ -591,7 +1936,7 @@ mod tests {
 
         let result = vm.interpret("print \"hello world\"");
 
-        assert_eq!(result, InterpretResult::CompileError);
+        assert!(matches!(result, InterpretResult::CompileError(_)));
     }
 
     #[test]
@@ -609,7 +1954,7 @@ mod tests {
 
         let result = vm.interpret("if false {0;}");
 
-        assert_eq!(result, InterpretResult::CompileError);
+        assert!(matches!(result, InterpretResult::CompileError(_)));
     }
 
     #[test]
@@ -654,7 +1999,7 @@ mod tests {
 
         let result = vm.interpret("while false {0;}");
 
-        assert_eq!(result, InterpretResult::CompileError);
+        assert!(matches!(result, InterpretResult::CompileError(_)));
     }
 
     #[test]
@@ -666,12 +2011,313 @@ mod tests {
         assert_eq!(result, InterpretResult::Ok);
     }
 
+    #[test]
+    fn vm_interpret_call() {
+        let mut vm = VM::new();
+
+        let result = vm.interpret("fun f(a, b) { return a + b; } f(1, 2);");
+
+        assert_eq!(result, InterpretResult::Ok);
+    }
+
+    #[test]
+    fn vm_interpret_call_wrong_arity() {
+        let mut vm = VM::new();
+
+        let result = vm.interpret("fun f(a) {} f();");
+
+        assert!(matches!(result, InterpretResult::RuntimeError(_)));
+    }
+
+    #[test]
+    fn vm_interpret_call_non_function() {
+        let mut vm = VM::new();
+
+        let result = vm.interpret("var a = 1; a();");
+
+        assert!(matches!(result, InterpretResult::RuntimeError(_)));
+    }
+
+    #[test]
+    fn vm_interpret_call_local_function() {
+        let mut vm = VM::new();
+
+        let result = vm.interpret("{ fun f() { return 1; } var a = f(); }");
+
+        assert_eq!(result, InterpretResult::Ok);
+    }
+
+    #[test]
+    fn vm_interpret_stack_overflow() {
+        let mut vm = VM::new();
+
+        let result = vm.interpret("fun f() { f(); } f();");
+
+        assert!(matches!(result, InterpretResult::RuntimeError(_)));
+    }
+
+    #[test]
+    fn vm_interpret_return_at_top_level() {
+        let mut vm = VM::new();
+
+        let result = vm.interpret("return 1;");
+
+        assert!(matches!(result, InterpretResult::CompileError(_)));
+    }
+
+    #[test]
+    fn vm_interpret_closure() {
+        let mut vm = VM::new();
+
+        let result = vm
+            .interpret("fun f() { var a = 1; fun g() { a = a + 1; return a; } return g; } f()();");
+
+        assert_eq!(result, InterpretResult::Ok);
+        assert!(vm.open_upvalues.is_empty());
+    }
+
+    #[test]
+    fn vm_close_upvalues() {
+        let mut vm = VM::new();
+        vm.push(Value::Number(1.0));
+        vm.push(Value::Number(2.0));
+        let first = vm.capture_upvalue(0);
+        let second = vm.capture_upvalue(1);
+
+        vm.close_upvalues(1);
+
+        assert_eq!(*vm.heap.get(first), Upvalue::Open(0));
+        assert_eq!(*vm.heap.get(second), Upvalue::Closed(Value::Number(2.0)));
+        assert_eq!(vm.open_upvalues.len(), 1);
+    }
+
+    #[test]
+    fn vm_capture_upvalue_reuses_open_upvalue() {
+        let mut vm = VM::new();
+        vm.push(Value::Number(1.0));
+
+        let first = vm.capture_upvalue(0);
+        let second = vm.capture_upvalue(0);
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn vm_interpret_class() {
+        let mut vm = VM::new();
+
+        let result = vm
+            .interpret("class A { init(a) { this.a = a; } get() { return this.a; } } A(1).get();");
+
+        assert_eq!(result, InterpretResult::Ok);
+    }
+
+    #[test]
+    fn vm_interpret_get_undefined_property() {
+        let mut vm = VM::new();
+
+        let result = vm.interpret("class A {} A().a;");
+
+        assert!(matches!(result, InterpretResult::RuntimeError(_)));
+    }
+
+    #[test]
+    fn vm_interpret_set_property_on_non_instance() {
+        let mut vm = VM::new();
+
+        let result = vm.interpret("var a = 1; a.b = 2;");
+
+        assert!(matches!(result, InterpretResult::RuntimeError(_)));
+    }
+
+    #[test]
+    fn vm_interpret_this_outside_class() {
+        let mut vm = VM::new();
+
+        let result = vm.interpret("print this;");
+
+        assert!(matches!(result, InterpretResult::CompileError(_)));
+    }
+
+    #[test]
+    fn vm_interpret_inherit() {
+        let mut vm = VM::new();
+
+        let result = vm.interpret(
+            "class A { f() { return 1; } } class B < A { f() { return super.f(); } } B().f();",
+        );
+
+        assert_eq!(result, InterpretResult::Ok);
+    }
+
+    #[test]
+    fn vm_interpret_inherit_from_non_class() {
+        let mut vm = VM::new();
+
+        let result = vm.interpret("var A = 1; class B < A {}");
+
+        assert!(matches!(result, InterpretResult::RuntimeError(_)));
+    }
+
+    #[test]
+    fn vm_interpret_super_without_superclass() {
+        let mut vm = VM::new();
+
+        let result = vm.interpret("class A { f() { super.f(); } }");
+
+        assert!(matches!(result, InterpretResult::CompileError(_)));
+    }
+
     #[test]
     fn vm_interpret_for_syntax_error() {
         let mut vm = VM::new();
 
         let result = vm.interpret("for{0;}");
 
-        assert_eq!(result, InterpretResult::CompileError);
+        assert!(matches!(result, InterpretResult::CompileError(_)));
+    }
+
+    #[test]
+    fn vm_interpret_with_stress_gc() {
+        let mut vm = VM::new();
+        vm.set_stress_gc(true);
+
+        let result = vm.interpret(
+            "class A { init(n) { this.n = n; } get() { return this.n; } }
+            class B < A { get() { return \"b\" + super.get(); } }
+            fun counter() { var i = \"\"; fun inc() { i = i + \"i\"; return i; } return inc; }
+            var c = counter(); c(); c();
+            var b = B(c()); var get = b.get;
+            if (get() != \"biii\") { nil(); }",
+        );
+
+        assert_eq!(result, InterpretResult::Ok);
+    }
+
+    #[test]
+    fn vm_collects_unreachable_objects() {
+        let mut vm = VM::new();
+        vm.set_stress_gc(true);
+        let before = vm.heap.object_count();
+
+        let result = vm.interpret("for (var i = 0; i < 100; i = i + 1) { var s = \"a\" + \"b\"; }");
+
+        assert_eq!(result, InterpretResult::Ok);
+        assert!(vm.heap.object_count() < before + 10);
+    }
+
+    #[test]
+    fn vm_set_args() {
+        let mut vm = VM::new();
+        vm.set_args(&["one".to_string(), "two".to_string()]);
+
+        vm.collect_garbage();
+
+        let result = vm.interpret("var a = get(args, 1) + str(len(args));");
+        assert_eq!(result, InterpretResult::Ok);
+        let name = vm.heap.intern("a");
+        let two2 = vm.heap.intern("two2");
+        assert_eq!(vm.globals[&name], Value::Object(ObjectType::String(two2)));
+    }
+
+    #[test]
+    fn vm_collect_garbage_keeps_globals() {
+        let mut vm = VM::new();
+        vm.interpret("var a = \"a\" + \"b\"; class C {}");
+
+        vm.collect_garbage();
+
+        //The globals, their names, the natives and the init string
+        assert_eq!(vm.heap.object_count(), 17);
+        assert_eq!(vm.interpret("print a; print C;"), InterpretResult::Ok);
+    }
+
+    #[test]
+    fn vm_interpret_concatenated_strings_are_interned() {
+        let mut vm = VM::new();
+
+        let result = vm.interpret("var a = \"a\" + \"b\"; var b = \"ab\";");
+
+        assert_eq!(result, InterpretResult::Ok);
+        let a = vm.globals[&vm.heap.intern("a")];
+        let b = vm.globals[&vm.heap.intern("b")];
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn vm_define_native() {
+        fn double(_heap: &mut Heap, args: &[Value]) -> Result<Value, String> {
+            match args[0] {
+                Value::Number(n) => Ok(Value::Number(n * 2.0)),
+                _ => Err("Argument must be a number.".to_string()),
+            }
+        }
+        let mut vm = VM::new();
+
+        vm.define_native("double", 1, double);
+
+        assert_eq!(vm.interpret("var a = double(2);"), InterpretResult::Ok);
+        assert_eq!(vm.globals[&vm.heap.intern("a")], Value::Number(4.0));
+        assert!(matches!(
+            vm.interpret("double(nil);"),
+            InterpretResult::RuntimeError(_)
+        ));
+        assert!(matches!(
+            vm.interpret("double();"),
+            InterpretResult::RuntimeError(_)
+        ));
+    }
+
+    #[test]
+    fn vm_runtime_error_trace() {
+        let mut vm = VM::new();
+
+        let result = vm.interpret("fun a() {\n  b();\n}\nfun b() {\n  -nil;\n}\n\na();\n");
+
+        let error = match result {
+            InterpretResult::RuntimeError(e) => e,
+            _ => panic!("Expected a runtime error"),
+        };
+        assert_eq!(error.message, "Operand must be a number.");
+        assert_eq!(error.line, 5);
+        assert_eq!(
+            error.trace,
+            vec![
+                TraceFrame {
+                    function: Some("b".to_string()),
+                    line: 5
+                },
+                TraceFrame {
+                    function: Some("a".to_string()),
+                    line: 2
+                },
+                TraceFrame {
+                    function: None,
+                    line: 8
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn runtime_error_display() {
+        let error = RuntimeError::new(
+            "Oops.",
+            vec![
+                TraceFrame {
+                    function: Some("f".to_string()),
+                    line: 2,
+                },
+                TraceFrame {
+                    function: None,
+                    line: 4,
+                },
+            ],
+        );
+
+        assert_eq!(
+            error.to_string(),
+            "Oops.\n[line 2] in f()\n[line 4] in script"
+        );
     }
 }
@@ -0,0 +1,112 @@
+use std::path::Path;
+use std::process::{Command, Output};
+use std::str;
+
+fn compile_and_run(name: &str) -> Output {
+    let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.roxc", name));
+
+    let compiled = Command::new("cargo")
+        .args([
+            "run",
+            "-q",
+            "--release",
+            "--",
+            "compile",
+            &format!("tests/resources/bytecode/{}.lox", name),
+            "-o",
+            output.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Error while compiling bytecode");
+    assert!(compiled.status.success());
+
+    Command::new("cargo")
+        .args([
+            "run",
+            "-q",
+            "--release",
+            "--",
+            "run",
+            output.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Error while running bytecode")
+}
+
+#[test]
+fn program() {
+    let result = compile_and_run("program");
+
+    assert_eq!(
+        str::from_utf8(&result.stdout).unwrap(),
+        "12\n7\nab\nnil\ntrue\n"
+    );
+    assert!(result.status.success());
+}
+
+#[test]
+fn runtime_error() {
+    let result = compile_and_run("runtime_error");
+
+    assert_eq!(
+        str::from_utf8(&result.stderr).unwrap(),
+        "Operands must be two numbers or two strings.\n[line 2] in fail()\n[line 5] in script\n"
+    );
+    assert_eq!(result.status.code().unwrap(), 70);
+}
+
+#[test]
+fn truncated() {
+    let result = Command::new("cargo")
+        .args([
+            "run",
+            "-q",
+            "--release",
+            "--",
+            "run",
+            "tests/resources/bytecode/truncated.roxc",
+        ])
+        .output()
+        .expect("Error while running bytecode/truncated()");
+
+    assert_eq!(
+        str::from_utf8(&result.stderr).unwrap(),
+        "Invalid bytecode: Unexpected end of input at byte 5.\n"
+    );
+    assert_eq!(result.status.code().unwrap(), 65);
+}
+
+#[test]
+fn unknown_opcode() {
+    let result = Command::new("cargo")
+        .args([
+            "run",
+            "-q",
+            "--release",
+            "--",
+            "run",
+            "tests/resources/bytecode/unknown_opcode.roxc",
+        ])
+        .output()
+        .expect("Error while running bytecode/unknown_opcode()");
+
+    assert_eq!(
+        str::from_utf8(&result.stderr).unwrap(),
+        "Invalid bytecode: Unknown opcode 200 at offset 0 in <script>.\n"
+    );
+    assert_eq!(result.status.code().unwrap(), 65);
+}
+
+#[test]
+fn source_starting_with_magic() {
+    let result = Command::new("cargo")
+        .args(["run", "-q", "--release", "--", "-e", "ROXCfoo = 1;"])
+        .output()
+        .expect("Error while running bytecode/source_starting_with_magic()");
+
+    assert_eq!(
+        str::from_utf8(&result.stderr).unwrap(),
+        "Undefined variable 'ROXCfoo'.\n[line 1] in script\n"
+    );
+    assert_eq!(result.status.code().unwrap(), 70);
+}
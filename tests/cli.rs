@@ -0,0 +1,125 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::str;
+
+fn rox(args: &[&str]) -> Command {
+    let mut command = Command::new("cargo");
+    command.args(["run", "-q", "--release", "--"]).args(args);
+    command
+}
+
+#[test]
+fn run_with_args() {
+    let result = rox(&["run", "tests/resources/cli/args.lox", "one", "--two"])
+        .output()
+        .expect("Error while running cli/run_with_args()");
+
+    assert_eq!(str::from_utf8(&result.stdout).unwrap(), "2\none\n--two\n");
+    assert!(result.status.success());
+}
+
+#[test]
+fn path_with_args() {
+    let result = rox(&["tests/resources/cli/args.lox", "a"])
+        .output()
+        .expect("Error while running cli/path_with_args()");
+
+    assert_eq!(str::from_utf8(&result.stdout).unwrap(), "1\na\n");
+    assert!(result.status.success());
+}
+
+#[test]
+fn eval() {
+    let result = rox(&["-e", "print args;", "x", "y"])
+        .output()
+        .expect("Error while running cli/eval()");
+
+    assert_eq!(str::from_utf8(&result.stdout).unwrap(), "[x, y]\n");
+    assert!(result.status.success());
+}
+
+#[test]
+fn eval_compile_error() {
+    let result = rox(&["-e", "print;"])
+        .output()
+        .expect("Error while running cli/eval_compile_error()");
+
+    assert_eq!(
+        str::from_utf8(&result.stderr).unwrap(),
+        "[line 1] Error at ';': Expect expression.\n1 | print;\n  |      ^\n"
+    );
+    assert_eq!(result.status.code().unwrap(), 65);
+}
+
+#[test]
+fn eval_missing_code() {
+    let result = rox(&["-e"])
+        .output()
+        .expect("Error while running cli/eval_missing_code()");
+
+    assert_eq!(result.status.code().unwrap(), 64);
+}
+
+#[test]
+fn stdin() {
+    let mut child = rox(&["-", "arg"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Error while running cli/stdin()");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"print get(args, 0) + \"!\";")
+        .unwrap();
+
+    let result = child.wait_with_output().unwrap();
+
+    assert_eq!(str::from_utf8(&result.stdout).unwrap(), "arg!\n");
+    assert!(result.status.success());
+}
+
+#[test]
+fn disassemble() {
+    let result = rox(&["--disassemble", "-e", "print 1;"])
+        .output()
+        .expect("Error while running cli/disassemble()");
+
+    assert_eq!(
+        str::from_utf8(&result.stdout).unwrap(),
+        "== <script> ==
+0000    1 CONSTANT            0 1
+0002    | PRINT
+0003    | NIL
+0004    | RETURN
+1
+"
+    );
+    assert!(result.status.success());
+}
+
+#[test]
+fn version() {
+    let result = rox(&["--version"])
+        .output()
+        .expect("Error while running cli/version()");
+
+    assert_eq!(
+        str::from_utf8(&result.stdout).unwrap(),
+        format!("rox {}\n", env!("CARGO_PKG_VERSION"))
+    );
+    assert!(result.status.success());
+}
+
+#[test]
+fn unknown_option() {
+    let result = rox(&["--bogus"])
+        .output()
+        .expect("Error while running cli/unknown_option()");
+
+    assert!(str::from_utf8(&result.stderr)
+        .unwrap()
+        .starts_with("Usage: rox"));
+    assert_eq!(result.status.code().unwrap(), 64);
+}
@@ -0,0 +1,180 @@
+use rox_lib::output::Buffer;
+use rox_lib::vm::{InterpretResult, VM};
+use rox_lib::RoxError;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+//Runs every script in tests/resources in-process and checks it against the expectations written
+//in its comments, in the same format as the craftinginterpreters test suite:
+//
+//  print 1; // expect: 1
+//  nil.foo; // expect runtime error: Only instances have properties.
+//  // [line 2] Error at 'var': Expect expression.
+//  var a = a; // Error at 'a': Cannot read local variable in its own initializer.
+//
+//A compile error without a line is on the line of its comment, and one written as "Error:" with
+//no token matches any token. Errors only expected from the Java implementation are ignored
+#[test]
+fn golden() {
+    let mut paths = Vec::new();
+    find_scripts(Path::new("tests/resources"), &mut paths);
+
+    let failures: Vec<String> = paths
+        .iter()
+        .filter_map(|path| {
+            let source = fs::read_to_string(path).unwrap();
+            let problems = check(&source);
+            if problems.is_empty() {
+                None
+            } else {
+                Some(format!("{}:\n  {}", path.display(), problems.join("\n  ")))
+            }
+        })
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} scripts failed\n\n{}\n",
+        failures.len(),
+        paths.len(),
+        failures.join("\n\n")
+    );
+}
+
+fn find_scripts(dir: &Path, paths: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            find_scripts(&path, paths);
+        } else if path.extension().is_some_and(|e| e == "lox") {
+            paths.push(path);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Expectations {
+    output: Vec<String>,
+    compile_errors: Vec<(i32, String)>, //Line and the error after "[line N] "
+    runtime_error: Option<(i32, String)>,
+}
+
+fn parse_expectations(source: &str) -> Expectations {
+    let mut expectations = Expectations::default();
+
+    for (line, text) in (1..).zip(source.lines()) {
+        if let Some(output) = after(text, "// expect: ") {
+            expectations.output.push(output.to_string());
+        } else if let Some(message) = after(text, "// expect runtime error: ") {
+            expectations.runtime_error = Some((line, message.to_string()));
+        } else if let Some(error) = after(text, "// [line ").or_else(|| after(text, "// [c line "))
+        {
+            let (number, error) = error.split_once("] ").unwrap();
+            expectations
+                .compile_errors
+                .push((number.parse().unwrap(), error.to_string()));
+        } else if let Some(error) = after(text, "// Error") {
+            expectations
+                .compile_errors
+                .push((line, format!("Error{}", error)));
+        }
+    }
+
+    expectations
+}
+
+fn after<'a>(text: &'a str, marker: &str) -> Option<&'a str> {
+    text.find(marker).map(|i| &text[i + marker.len()..])
+}
+
+fn check(source: &str) -> Vec<String> {
+    let expectations = parse_expectations(source);
+    let mut problems = Vec::new();
+
+    let mut vm = VM::new();
+    let output = Buffer::new();
+    vm.set_output(output.clone());
+    vm.set_args(&[]);
+    let result = vm.interpret(source);
+
+    let output = output.take();
+    let output: Vec<&str> = output.lines().collect();
+    if output != expectations.output {
+        problems.push(format!(
+            "Expected output {:?} but got {:?}.",
+            expectations.output, output
+        ));
+    }
+
+    match (&result, &expectations) {
+        (
+            InterpretResult::Ok,
+            Expectations {
+                compile_errors,
+                runtime_error: None,
+                ..
+            },
+        ) if compile_errors.is_empty() => (),
+        (InterpretResult::CompileError(errors), Expectations { compile_errors, .. })
+            if !compile_errors.is_empty() =>
+        {
+            for (line, expected) in compile_errors {
+                if !errors.iter().any(|e| matches(e, *line, expected)) {
+                    problems.push(format!(
+                        "Missing expected error: [line {}] {}",
+                        line, expected
+                    ));
+                }
+            }
+            for error in errors {
+                if !compile_errors.iter().any(|(l, e)| matches(error, *l, e)) {
+                    problems.push(format!("Unexpected error: {}", describe(error)));
+                }
+            }
+        }
+        (
+            InterpretResult::RuntimeError(error),
+            Expectations {
+                runtime_error: Some((line, message)),
+                ..
+            },
+        ) => {
+            if error.message != *message || error.line != *line {
+                problems.push(format!(
+                    "Expected runtime error '{}' on line {} but got '{}' on line {}.",
+                    message, line, error.message, error.line
+                ));
+            }
+        }
+        _ => problems.push(format!("Unexpected result {:?}.", result)),
+    }
+
+    problems
+}
+
+//The error as it appears in the expectations, where the end of the source is written as "end"
+fn describe(error: &RoxError) -> String {
+    let token = if error.token.is_empty() {
+        "end".to_string()
+    } else {
+        format!("'{}'", error.token)
+    };
+    format!(
+        "[line {}] Error at {}: {}",
+        error.line(),
+        token,
+        error.message
+    )
+}
+
+fn matches(error: &RoxError, line: i32, expected: &str) -> bool {
+    match expected.strip_prefix("Error: ") {
+        Some(message) => error.line() == line && error.message == message,
+        None => describe(error) == format!("[line {}] {}", line, expected),
+    }
+}
@@ -4,7 +4,7 @@ use std::str;
 #[test]
 fn loop_too_large() {
     let result = Command::new("cargo")
-        .args(&[
+        .args([
             "run",
             "-q",
             "--release",
@@ -16,7 +16,7 @@ fn loop_too_large() {
 
     assert_eq!(
         str::from_utf8(&result.stderr).unwrap(),
-        "[line 2351] Error at '}': Loop body too large.\n"
+        "[line 2351] Error at '}': Loop body too large.\n2351 | } // Error at '}': Loop body too large.\n     | ^\n"
     );
     assert_eq!(result.status.code().unwrap(), 65);
 }
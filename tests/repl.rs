@@ -0,0 +1,105 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::str;
+
+fn repl(input: &str) -> Output {
+    let mut child = Command::new("cargo")
+        .args(["run", "-q", "--release"])
+        .env("HOME", env!("CARGO_TARGET_TMPDIR"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Error while starting the repl");
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child
+        .wait_with_output()
+        .expect("Error while running the repl")
+}
+
+#[test]
+fn expression() {
+    let result = repl("1 + 2;\nvar a = \"b\";\na;\n");
+
+    assert_eq!(str::from_utf8(&result.stdout).unwrap(), "3\nb\n");
+    assert!(result.status.success());
+}
+
+#[test]
+fn multi_line() {
+    let result = repl("fun f() {\nreturn 4;\n}\n{\nprint f();\n}\n");
+
+    assert_eq!(str::from_utf8(&result.stdout).unwrap(), "4\n");
+    assert!(result.status.success());
+}
+
+#[test]
+fn error_continues() {
+    let result = repl("+;\nprint 5;\n");
+
+    assert_eq!(str::from_utf8(&result.stdout).unwrap(), "5\n");
+    assert_eq!(
+        str::from_utf8(&result.stderr).unwrap(),
+        "[line 1] Error at '+': Expect expression.\n1 | +;\n  | ^\n"
+    );
+    assert!(result.status.success());
+}
+
+#[test]
+fn globals_command() {
+    let result = repl("var a = 1;\n:reset\nvar b = \"c\";\n:globals\n");
+
+    let stdout = str::from_utf8(&result.stdout).unwrap();
+    assert!(stdout.contains("b = c\n"));
+    assert!(stdout.contains("clock = <native fn>\n"));
+    assert!(!stdout.contains("a = 1\n"));
+    assert!(result.status.success());
+}
+
+#[test]
+fn type_command() {
+    let result = repl("fun f() {}\n:type f\n:type 1 + 2\n:type nil\n");
+
+    assert_eq!(
+        str::from_utf8(&result.stdout).unwrap(),
+        "function\nnumber\nnil\n"
+    );
+    assert!(result.status.success());
+}
+
+#[test]
+fn dis_command() {
+    let result = repl(":dis 1 + 2\n");
+
+    assert_eq!(
+        str::from_utf8(&result.stdout).unwrap(),
+        "== <script> ==\n0000    1 CONSTANT            0 1\n0002    | CONSTANT            1 2\n0004    | ADD\n0005    | RETURN\n"
+    );
+    assert!(result.status.success());
+}
+
+#[test]
+fn load_command() {
+    let result = repl(":load tests/resources/cli/args.lox\n:time print 2;\n");
+
+    let stdout = str::from_utf8(&result.stdout).unwrap();
+    assert!(stdout.starts_with("0\n2\nTook "));
+    assert!(result.status.success());
+}
+
+#[test]
+fn unknown_command() {
+    let result = repl(":nope\n");
+
+    assert_eq!(
+        str::from_utf8(&result.stderr).unwrap(),
+        "Unknown command ':nope', see :help.\n"
+    );
+    assert!(result.status.success());
+}
@@ -0,0 +1,93 @@
+use std::process::Command;
+use std::str;
+
+#[test]
+fn dump() {
+    let result = Command::new("cargo")
+        .args([
+            "run",
+            "-q",
+            "--release",
+            "--",
+            "--dump",
+            "tests/resources/trace/add.lox",
+        ])
+        .output()
+        .expect("Error while running trace/dump()");
+
+    assert_eq!(
+        str::from_utf8(&result.stdout).unwrap(),
+        "== <script> ==
+0000    1 CONSTANT            0 1
+0002    | CONSTANT            1 2
+0004    | ADD
+0005    | PRINT
+0006    2 NIL
+0007    | RETURN
+3
+"
+    );
+    assert!(result.status.success());
+}
+
+#[test]
+fn trace() {
+    let result = Command::new("cargo")
+        .args([
+            "run",
+            "-q",
+            "--release",
+            "--",
+            "--trace",
+            "tests/resources/trace/add.lox",
+        ])
+        .output()
+        .expect("Error while running trace/trace()");
+
+    assert_eq!(
+        str::from_utf8(&result.stdout).unwrap(),
+        "          [ <script> ]
+0000    1 CONSTANT            0 1
+          [ <script> ][ 1 ]
+0002    | CONSTANT            1 2
+          [ <script> ][ 1 ][ 2 ]
+0004    | ADD
+          [ <script> ][ 3 ]
+0005    | PRINT
+3
+          [ <script> ]
+0006    2 NIL
+          [ <script> ][ nil ]
+0007    | RETURN
+"
+    );
+    assert!(result.status.success());
+}
+
+#[test]
+fn debug_build_is_quiet() {
+    let result = Command::new("cargo")
+        .args(["run", "-q", "--", "tests/resources/trace/add.lox"])
+        .output()
+        .expect("Error while running trace/debug_build_is_quiet()");
+
+    assert_eq!(str::from_utf8(&result.stdout).unwrap(), "3\n");
+    assert!(result.status.success());
+}
+
+#[test]
+fn unknown_flag() {
+    let result = Command::new("cargo")
+        .args([
+            "run",
+            "-q",
+            "--release",
+            "--",
+            "--verbose",
+            "tests/resources/trace/add.lox",
+        ])
+        .output()
+        .expect("Error while running trace/unknown_flag()");
+
+    assert_eq!(result.status.code().unwrap(), 64);
+}